@@ -2,6 +2,7 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    println!("cargo::rustc-check-cfg=cfg(astrobox_priv_cloned)");
     emit_priv_cfg_flag();
     println!("cargo::rustc-check-cfg=cfg(rust_analyzer)");
 }
@@ -0,0 +1,85 @@
+use js_sys::{Object, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{BluetoothAdvertisingEvent, Navigator, window};
+
+use crate::error::{DeviceError, ErrorCode, picker_error};
+
+/// Callback invoked with `(name, id, rssi)` for every advertisement seen.
+pub type AdvertisementCallback = Box<dyn Fn(Option<String>, String, Option<i8>)>;
+
+/// A running `requestLEScan`. web-sys has no binding for it yet, so it is
+/// driven through `Reflect`; browsers without it reject with `PortOpenFailed`.
+pub struct BleScan {
+    scan: JsValue,
+    on_advertisement: Closure<dyn FnMut(JsValue)>,
+}
+
+impl BleScan {
+    pub async fn start(callback: AdvertisementCallback) -> Result<Self, DeviceError> {
+        let nav: Navigator = window().unwrap().navigator();
+        let bluetooth = nav.bluetooth().ok_or_else(|| {
+            DeviceError::new(
+                ErrorCode::PortOpenFailed,
+                "Web Bluetooth is not available in this browser",
+            )
+        })?;
+        let request = Reflect::get(&bluetooth, &JsValue::from_str("requestLEScan"))
+            .ok()
+            .and_then(|func| func.dyn_into::<js_sys::Function>().ok())
+            .ok_or_else(|| {
+                DeviceError::new(
+                    ErrorCode::PortOpenFailed,
+                    "Bluetooth scanning is not supported in this browser",
+                )
+            })?;
+
+        let on_advertisement = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let Some(event) = event.dyn_ref::<BluetoothAdvertisingEvent>() else {
+                return;
+            };
+            let device = event.device();
+            let name = event.name().or_else(|| device.name());
+            callback(name, device.id(), event.rssi());
+        });
+        bluetooth.set_onadvertisementreceived(Some(on_advertisement.as_ref().unchecked_ref()));
+
+        let options = Object::new();
+        let _ = Reflect::set(
+            &options,
+            &JsValue::from_str("acceptAllAdvertisements"),
+            &JsValue::TRUE,
+        );
+        let started = request
+            .call1(&bluetooth, &options)
+            .and_then(|promise| promise.dyn_into::<Promise>())
+            .map(JsFuture::from);
+        let scan = match started {
+            Ok(fut) => fut.await,
+            Err(err) => Err(err),
+        };
+        match scan {
+            Ok(scan) => Ok(Self {
+                scan,
+                on_advertisement,
+            }),
+            Err(err) => {
+                bluetooth.set_onadvertisementreceived(None);
+                Err(picker_error(&err))
+            }
+        }
+    }
+
+    pub fn stop(self) {
+        if let Ok(stop) = Reflect::get(&self.scan, &JsValue::from_str("stop"))
+            .and_then(|func| func.dyn_into::<js_sys::Function>())
+        {
+            let _ = stop.call0(&self.scan);
+        }
+        if let Some(bluetooth) = window().unwrap().navigator().bluetooth() {
+            bluetooth.set_onadvertisementreceived(None);
+        }
+        drop(self.on_advertisement);
+    }
+}
@@ -0,0 +1,334 @@
+use async_channel::{Receiver, Sender, bounded};
+use corelib::device::xiaomi::r#type::ConnectType;
+use corelib::device::{self, DeviceConnectionInfo, DeviceKind};
+use js_sys::{JsString, Reflect, Uint8Array};
+use tokio::sync::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Bluetooth, BluetoothDevice, BluetoothLeScanFilterInit, BluetoothRemoteGattCharacteristic,
+    BluetoothRemoteGattServer, Navigator, RequestDeviceOptions, window,
+};
+
+use crate::error::{DeviceError, ErrorCode, js_error_message, picker_error};
+use crate::spp::xiaomi::{
+    ConnectionState, DEFAULT_DEVICE_NAME, DEFAULT_WRITE_QUEUE_DEPTH, DisconnectReason,
+    SessionCallbacks, WriteCommand, dispatch_packet,
+};
+use crate::timer::sleep_ms;
+
+const XIAOMI_SERVICE_UUID: &str = "0000fe95-0000-1000-8000-00805f9b34fb";
+const XIAOMI_READ_CHAR_UUID: &str = "00000051-0000-1000-8000-00805f9b34fb";
+const XIAOMI_WRITE_CHAR_UUID: &str = "00000052-0000-1000-8000-00805f9b34fb";
+
+const WRITE_DRAIN_TIMEOUT_MS: u32 = 2_000;
+
+fn bluetooth() -> Result<Bluetooth, DeviceError> {
+    let nav: Navigator = window().unwrap().navigator();
+    nav.bluetooth().ok_or_else(|| {
+        DeviceError::new(
+            ErrorCode::PortOpenFailed,
+            "Web Bluetooth is not available in this browser",
+        )
+    })
+}
+
+pub struct XiaomiBle {
+    device: BluetoothDevice,
+    server: BluetoothRemoteGattServer,
+    read_char: BluetoothRemoteGattCharacteristic,
+    write_char: BluetoothRemoteGattCharacteristic,
+    device_addr: String,
+    device_label: Option<String>,
+    write_queue_depth: usize,
+    write_tx: Option<Sender<WriteCommand>>,
+    write_done: Option<oneshot::Receiver<()>>,
+    on_value: Option<Closure<dyn FnMut(JsValue)>>,
+    on_gatt_disconnected: Option<Closure<dyn FnMut(JsValue)>>,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl XiaomiBle {
+    pub async fn new() -> Result<Self, DeviceError> {
+        let filter = BluetoothLeScanFilterInit::new();
+        filter.set_services(&[JsString::from(XIAOMI_SERVICE_UUID)]);
+        let opts = RequestDeviceOptions::new();
+        opts.set_filters(&[filter]);
+        opts.set_optional_services(&[JsString::from(XIAOMI_SERVICE_UUID)]);
+
+        let device: BluetoothDevice = JsFuture::from(bluetooth()?.request_device(&opts))
+            .await
+            .map_err(|err| picker_error(&err))?
+            .unchecked_into();
+        Self::open(device).await
+    }
+
+    async fn open(device: BluetoothDevice) -> Result<Self, DeviceError> {
+        let gatt = device.gatt().ok_or_else(|| {
+            DeviceError::new(ErrorCode::PortOpenFailed, "Device has no GATT server")
+        })?;
+        let server: BluetoothRemoteGattServer = JsFuture::from(gatt.connect())
+            .await
+            .map_err(|err| DeviceError::from_js(ErrorCode::PortOpenFailed, &err))?
+            .unchecked_into();
+
+        let open_err = |err: JsValue| {
+            server.disconnect();
+            DeviceError::from_js(ErrorCode::PortOpenFailed, &err)
+        };
+        let service: web_sys::BluetoothRemoteGattService =
+            JsFuture::from(server.get_primary_service_with_str(XIAOMI_SERVICE_UUID))
+                .await
+                .map_err(open_err)?
+                .unchecked_into();
+        let read_char: BluetoothRemoteGattCharacteristic =
+            JsFuture::from(service.get_characteristic_with_str(XIAOMI_READ_CHAR_UUID))
+                .await
+                .map_err(open_err)?
+                .unchecked_into();
+        let write_char: BluetoothRemoteGattCharacteristic =
+            JsFuture::from(service.get_characteristic_with_str(XIAOMI_WRITE_CHAR_UUID))
+                .await
+                .map_err(open_err)?
+                .unchecked_into();
+
+        let device_addr = format!("ble:{}", device.id());
+        let device_label = device.name().filter(|name| !name.is_empty());
+
+        Ok(Self {
+            device,
+            server,
+            read_char,
+            write_char,
+            device_addr,
+            device_label,
+            write_queue_depth: DEFAULT_WRITE_QUEUE_DEPTH,
+            write_tx: None,
+            write_done: None,
+            on_value: None,
+            on_gatt_disconnected: None,
+            runtime: None,
+        })
+    }
+
+    pub fn set_write_queue_depth(&mut self, depth: usize) {
+        self.write_queue_depth = depth.max(1);
+    }
+
+    /// Handle to the queue feeding the write characteristic, shared with corelib.
+    pub fn write_sender(&self) -> Option<Sender<WriteCommand>> {
+        self.write_tx.clone()
+    }
+
+    pub fn device_addr(&self) -> &str {
+        &self.device_addr
+    }
+
+    pub fn device_label(&self) -> Option<&str> {
+        self.device_label.as_deref()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn start(
+        &mut self,
+        mut name: String,
+        addr_hint: String,
+        authkey: String,
+        sar_version: u32,
+        connect_type: ConnectType,
+        tx_win_overrun_allowance: u8,
+        callbacks: SessionCallbacks,
+    ) -> Result<DeviceConnectionInfo, DeviceError> {
+        let final_addr = if addr_hint.trim().is_empty() {
+            self.device_addr.clone()
+        } else {
+            addr_hint
+        };
+        (callbacks.on_state)(&final_addr, ConnectionState::Opening);
+
+        let (tx, rx): (Sender<WriteCommand>, Receiver<WriteCommand>) =
+            bounded(self.write_queue_depth);
+        let (done_tx, done_rx) = oneshot::channel();
+        self.write_tx = Some(tx.clone());
+        self.write_done = Some(done_rx);
+
+        let write_char = self.write_char.clone();
+        let write_error_handle = callbacks.on_link_error.clone();
+        let write_disconnect_handle = callbacks.on_disconnect.clone();
+        let outbound_handle = callbacks.on_outbound.clone();
+        let device_id_for_writes = final_addr.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Ok(command) = rx.recv().await {
+                let data = match command {
+                    WriteCommand::Data(data) => data,
+                    WriteCommand::Flush(ack) => {
+                        let _ = ack.send(());
+                        continue;
+                    }
+                };
+                let data = match outbound_handle.as_ref() {
+                    Some(outbound) => outbound(&device_id_for_writes, data),
+                    None => data,
+                };
+                // Each queued frame is one ATT write; corelib sizes BLE frames itself.
+                let write = write_char
+                    .write_value_without_response_with_u8_slice(&data)
+                    .map(JsFuture::from);
+                let res = match write {
+                    Ok(fut) => fut.await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = res {
+                    web_sys::console::warn_1(&JsValue::from_str(&format!(
+                        "[wasm] Failed to write to BLE characteristic: {:?}",
+                        err
+                    )));
+                    write_error_handle(&device_id_for_writes, "write", &js_error_message(&err));
+                    write_disconnect_handle(device_id_for_writes.clone(), DisconnectReason::Error);
+                    break;
+                }
+            }
+            let _ = done_tx.send(());
+        });
+
+        corelib::ecs::init_runtime_default();
+        let runtime = corelib::asyncrt::build_runtime();
+        let handle = runtime.handle().clone();
+
+        if name.is_empty() {
+            name = self
+                .device_label
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DEVICE_NAME.to_string());
+        }
+
+        let packet_handle = handle.clone();
+        let inbound_handle = callbacks.on_inbound.clone();
+        let device_id_for_packets = final_addr.clone();
+        let on_value = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let value = Reflect::get(&event, &JsValue::from_str("target"))
+                .and_then(|target| Reflect::get(&target, &JsValue::from_str("value")))
+                .unwrap_or(JsValue::UNDEFINED);
+            let Some(view) = value.dyn_ref::<js_sys::DataView>() else {
+                return;
+            };
+            let data = Uint8Array::new_with_byte_offset_and_length(
+                &view.buffer(),
+                view.byte_offset() as u32,
+                view.byte_length() as u32,
+            )
+            .to_vec();
+            log::trace!("[wasm] Recv: {}", corelib::tools::to_hex_string(&data));
+            if let Some(inbound) = inbound_handle.as_ref() {
+                inbound(&device_id_for_packets, &data);
+            }
+            dispatch_packet(&packet_handle, &device_id_for_packets, data);
+        });
+        self.read_char
+            .set_oncharacteristicvaluechanged(Some(on_value.as_ref().unchecked_ref()));
+        self.on_value = Some(on_value);
+
+        let disconnect_handle = callbacks.on_disconnect.clone();
+        let device_id_for_disconnect = final_addr.clone();
+        let on_gatt_disconnected = Closure::<dyn FnMut(JsValue)>::new(move |_event: JsValue| {
+            disconnect_handle(device_id_for_disconnect.clone(), DisconnectReason::Remote);
+        });
+        self.device
+            .set_ongattserverdisconnected(Some(on_gatt_disconnected.as_ref().unchecked_ref()));
+        self.on_gatt_disconnected = Some(on_gatt_disconnected);
+
+        if let Err(err) = JsFuture::from(self.read_char.start_notifications()).await {
+            self.release_handlers();
+            self.server.disconnect();
+            (callbacks.on_state)(&final_addr, ConnectionState::Error);
+            return Err(DeviceError::from_js(ErrorCode::PortOpenFailed, &err));
+        }
+
+        (callbacks.on_state)(&final_addr, ConnectionState::Authenticating);
+        let device_info_res = device::create_device(
+            handle.clone(),
+            DeviceKind::Xiaomi,
+            name.clone(),
+            final_addr.clone(),
+            authkey,
+            sar_version,
+            connect_type,
+            Some(tx_win_overrun_allowance),
+            None,
+            None,
+            false,
+            {
+                let tx = tx.clone();
+                move |data: Vec<Vec<u8>>| {
+                    let tx = tx.clone();
+                    async move {
+                        for item in data {
+                            if !item.is_empty() {
+                                log::trace!(
+                                    "[wasm] Send: {}",
+                                    corelib::tools::to_hex_string(&item)
+                                );
+                                let _ = tx.send(WriteCommand::Data(item)).await;
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            },
+        )
+        .await;
+
+        let device_info = match device_info_res {
+            Ok(info) => info,
+            Err(err) => {
+                web_sys::console::error_1(&JsValue::from_str(&format!(
+                    "[wasm] create_device failed: {}",
+                    err
+                )));
+                self.release_handlers();
+                self.server.disconnect();
+                (callbacks.on_state)(&final_addr, ConnectionState::Error);
+                return Err(DeviceError::from_core_connect(err));
+            }
+        };
+
+        (callbacks.on_state)(&final_addr, ConnectionState::Ready);
+
+        self.runtime = Some(runtime);
+
+        Ok(device_info)
+    }
+
+    fn release_handlers(&mut self) {
+        self.read_char.set_oncharacteristicvaluechanged(None);
+        self.device.set_ongattserverdisconnected(None);
+        self.on_value.take();
+        self.on_gatt_disconnected.take();
+    }
+
+    pub async fn disconnect(mut self) -> Result<(), JsValue> {
+        if let Some(tx) = self.write_tx.take() {
+            tx.close();
+        }
+        if let Some(done) = self.write_done.take() {
+            tokio::select! {
+                biased;
+                _ = done => {}
+                _ = sleep_ms(WRITE_DRAIN_TIMEOUT_MS) => {
+                    web_sys::console::warn_1(&JsValue::from_str(
+                        "[wasm] Timed out flushing BLE writes before disconnect",
+                    ));
+                }
+            }
+        }
+        // Detach first so a local disconnect is not reported as a remote one.
+        self.release_handlers();
+        if self.server.connected() {
+            let _ = JsFuture::from(self.read_char.stop_notifications()).await;
+            self.server.disconnect();
+        }
+        self.runtime.take();
+        Ok(())
+    }
+}
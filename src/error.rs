@@ -0,0 +1,153 @@
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use wasm_bindgen::{JsCast, JsValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorCode {
+    PortOpenFailed,
+    PortNotCached,
+    PortSelectionCancelled,
+    AuthRejected,
+    SarHandshakeTimeout,
+    ConnectTimeout,
+    DeviceNotFound,
+    WatchfaceNotFound,
+    AppNotFound,
+    DeviceBusy,
+    TooManySessions,
+    InvalidArgument,
+    InvalidPackage,
+    InsufficientStorage,
+    /// The device or its firmware does not report what was asked for.
+    Unsupported,
+    Timeout,
+    Cancelled,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn recoverable(self) -> bool {
+        matches!(
+            self,
+            Self::PortOpenFailed
+                | Self::SarHandshakeTimeout
+                | Self::ConnectTimeout
+                | Self::Timeout
+                | Self::DeviceBusy
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub recoverable: bool,
+    /// What the user can do about it, when there is something specific.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    /// Set on `AuthRejected`: whether the device is waiting for the pairing
+    /// to be confirmed on the watch rather than refusing the authkey outright.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pairing_requested: Option<bool>,
+}
+
+impl DeviceError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            recoverable: code.recoverable(),
+            hint: None,
+            pairing_requested: None,
+        }
+    }
+
+    fn auth_rejected(message: String, lower: &str) -> Self {
+        // corelib only reports the rejection as text, so a pending
+        // confirmation is told apart by its wording.
+        let pairing_requested = lower.contains("confirm") || lower.contains("pairing");
+        let hint = if pairing_requested {
+            "Confirm the pairing prompt on the watch, then connect again"
+        } else {
+            "The authkey was rejected; re-pair the watch to get a new one"
+        };
+        Self {
+            hint: Some(hint.to_string()),
+            pairing_requested: Some(pairing_requested),
+            ..Self::new(ErrorCode::AuthRejected, message)
+        }
+    }
+
+    pub fn from_js(code: ErrorCode, err: &JsValue) -> Self {
+        Self::new(code, js_error_message(err))
+    }
+
+    /// Maps an error surfaced by corelib to a known code by its text; anything
+    /// unrecognised keeps its `Display` output under `Internal`.
+    pub fn from_core(err: impl std::fmt::Display) -> Self {
+        Self::classify_core(err, ErrorCode::Timeout)
+    }
+
+    /// `from_core` for `create_device`, where a timeout means the SAR
+    /// handshake never completed.
+    pub fn from_core_connect(err: impl std::fmt::Display) -> Self {
+        Self::classify_core(err, ErrorCode::SarHandshakeTimeout)
+    }
+
+    fn classify_core(err: impl std::fmt::Display, timeout: ErrorCode) -> Self {
+        let message = err.to_string();
+        let lower = message.to_ascii_lowercase();
+        let code = if lower.contains("device not found") {
+            ErrorCode::DeviceNotFound
+        } else if lower.contains("busy") || lower.contains("occupied") {
+            // The watch refuses transfers while it is installing from another source.
+            ErrorCode::DeviceBusy
+        } else if lower.contains("auth") {
+            return Self::auth_rejected(message, &lower);
+        } else if lower.contains("timeout") || lower.contains("timed out") {
+            timeout
+        } else {
+            ErrorCode::Internal
+        };
+        Self::new(code, message)
+    }
+}
+
+impl std::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl From<DeviceError> for JsValue {
+    fn from(err: DeviceError) -> Self {
+        to_js_value(&err).unwrap_or_else(|_| JsValue::from_str(&err.message))
+    }
+}
+
+/// The `name` of a rejected DOM promise's error, e.g. `NotFoundError`.
+pub fn js_error_name(err: &JsValue) -> Option<String> {
+    err.dyn_ref::<js_sys::Error>()
+        .map(|js_err| String::from(js_err.name()))
+}
+
+/// Maps a rejected device picker request; dismissing the picker rejects
+/// with `NotFoundError`, which gets its own code so hosts can ignore it.
+pub fn picker_error(err: &JsValue) -> DeviceError {
+    if js_error_name(err).as_deref() == Some("NotFoundError") {
+        DeviceError::new(ErrorCode::PortSelectionCancelled, js_error_message(err))
+    } else {
+        DeviceError::from_js(ErrorCode::PortOpenFailed, err)
+    }
+}
+
+pub fn js_error_message(err: &JsValue) -> String {
+    if let Some(text) = err.as_string() {
+        return text;
+    }
+    if let Some(js_err) = err.dyn_ref::<js_sys::Error>() {
+        return String::from(js_err.message());
+    }
+    format!("{:?}", err)
+}
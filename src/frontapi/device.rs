@@ -1,52 +1,269 @@
-use async_channel::unbounded;
 use corelib::device::xiaomi::components::info::InfoSystem;
 use corelib::device::xiaomi::components::install::InstallSystem;
-use corelib::device::xiaomi::components::mass::SendMassCallbackData;
 use corelib::device::xiaomi::components::resource::{ResourceComponent, ResourceSystem};
 use corelib::device::xiaomi::components::thirdparty_app::ThirdpartyAppSystem;
 use corelib::device::xiaomi::components::watchface::WatchfaceSystem;
-use corelib::device::xiaomi::packet::mass::MassDataType;
 use corelib::device::xiaomi::resutils::{FileType, get_file_type};
 use corelib::device::xiaomi::r#type::ConnectType;
 use corelib::device::{Device, DeviceConnectionInfo, DeviceKind, cleanup_device_state};
-use js_sys::{Function, Uint8Array};
+use js_sys::Uint8Array;
 use once_cell::sync::OnceCell;
-use serde_wasm_bindgen::to_value as to_js_value;
-use std::sync::Arc;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
-use tokio::sync::oneshot;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value as from_js_value, to_value as to_js_value};
+use std::{cell::Cell, cell::RefCell, collections::HashMap, rc::Rc};
+use tokio::sync::{Mutex, oneshot};
 use wasm_bindgen::JsValue;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
+use web_sys::{AbortSignal, SerialOutputSignals, SerialPort};
 
-use crate::spp::xiaomi::XiaomiSpp;
+use crate::ble::xiaomi::XiaomiBle;
+use crate::error::{DeviceError, ErrorCode};
+#[cfg(feature = "mock")]
+use crate::mock::xiaomi::XiaomiMock;
+use crate::spp::xiaomi::{
+    ConnectionState, DisconnectReason, PortIdentity, SessionCallbacks, WriteCommand, XiaomiSpp,
+    granted_ports, resolve_baud_rate, resolve_buffer_size, resolve_write_queue_depth,
+};
+use crate::timer::sleep_ms;
+use events::emit_event;
 
+pub mod battery;
+pub mod capabilities;
+#[cfg(astrobox_priv_cloned)]
+pub mod debug;
+pub mod events;
+pub mod firmware;
+mod idle;
+pub mod install;
+mod keepalive;
+pub mod logging;
+pub mod overview;
+pub mod package;
+mod paging;
+#[cfg(astrobox_priv_cloned)]
+pub mod raw;
+pub mod reconnect;
+pub mod scan;
+pub mod storage;
 pub mod thirdparty_app;
 pub mod watchface;
 
 static CORE_INIT: OnceCell<()> = OnceCell::new();
 
+type EventHandler = Closure<dyn FnMut(JsValue)>;
+
 thread_local! {
-    static EVENT_SINK: RefCell<Option<js_sys::Function>> = RefCell::new(None);
-    static SESSIONS: RefCell<HashMap<String, XiaomiSpp>> = RefCell::new(HashMap::new());
+    static SESSIONS: RefCell<HashMap<String, Session>> = RefCell::new(HashMap::new());
+    static KNOWN_PORTS: RefCell<HashMap<String, KnownPort>> = RefCell::new(HashMap::new());
+    static LABELS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    static GRANTED_PORTS: RefCell<Vec<(u32, SerialPort)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_PORT_ID: Cell<u32> = const { Cell::new(1) };
+    static REQUEST_ATTEMPTS: Cell<u32> = const { Cell::new(DEFAULT_REQUEST_ATTEMPTS) };
+    static MAX_SESSIONS: Cell<Option<usize>> = const { Cell::new(None) };
+    static UNLOAD_HANDLER: RefCell<Option<EventHandler>> = const { RefCell::new(None) };
+    static COMMAND_LOCKS: RefCell<HashMap<String, Rc<Mutex<()>>>> = RefCell::new(HashMap::new());
+}
+
+/// Optional transport settings accepted by `device_connect` as a plain object.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct ConnectOptions {
+    baud_rate: Option<u32>,
+    /// Serial read/write buffer in bytes, 16 KiB by default.
+    buffer_size: Option<u32>,
+    /// `[vendor_id, product_id]` pairs restricting the port picker.
+    filters: Vec<(u16, u16)>,
+    /// Outgoing frames buffered before senders wait, 64 by default.
+    write_queue_depth: Option<usize>,
+    /// Status ping period in ms; the keepalive is off unless this is set
+    /// above 0.
+    keepalive_interval_ms: Option<u32>,
+    /// Consecutive failed pings before the session is dropped, 3 by default.
+    keepalive_max_failures: Option<u32>,
+    /// Name stored on the device entity and reported in events; wins over
+    /// the `name` argument and any earlier `device_set_label`.
+    display_name: Option<String>,
+    /// Upper bound on the handshake once the port is open, 20s by default.
+    timeout_ms: Option<u32>,
+    /// Disconnects after this long without commands or inbound frames; off by default.
+    idle_timeout_ms: Option<u32>,
+    /// Serial reads retried before the link counts as dead, 2 by default.
+    read_retries: Option<u32>,
+    /// Holds back `device-connected` until the device answers a status
+    /// request, instead of firing as soon as the handshake completes.
+    wait_ready: bool,
+    /// How long `wait_ready` keeps probing before giving up, 10s by default.
+    ready_timeout_ms: Option<u32>,
+}
+
+#[derive(Clone)]
+struct ConnectParams {
+    name: String,
+    authkey: String,
+    sar_version: u32,
+    connect_type: String,
+    tx_win_overrun_allowance: u8,
+    options: ConnectOptions,
+}
+
+#[derive(Clone)]
+struct KnownPort {
+    port: SerialPort,
+    identity: PortIdentity,
+    params: ConnectParams,
+}
+
+/// A connected transport; both speak the same corelib packet protocol.
+enum Session {
+    Spp(XiaomiSpp),
+    Ble(XiaomiBle),
+    #[cfg(feature = "mock")]
+    Mock(XiaomiMock),
+}
+
+impl Session {
+    fn device_addr(&self) -> &str {
+        match self {
+            Self::Spp(spp) => spp.device_addr(),
+            Self::Ble(ble) => ble.device_addr(),
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => mock.device_addr(),
+        }
+    }
+
+    fn write_sender(&self) -> Option<async_channel::Sender<WriteCommand>> {
+        match self {
+            Self::Spp(spp) => spp.write_sender(),
+            Self::Ble(ble) => ble.write_sender(),
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => mock.write_sender(),
+        }
+    }
+
+    /// What the transport calls the device, e.g. a USB serial number;
+    /// kept apart from the display name for diagnostics.
+    fn device_label(&self) -> Option<&str> {
+        match self {
+            Self::Spp(spp) => spp.device_label(),
+            Self::Ble(ble) => ble.device_label(),
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => mock.device_label(),
+        }
+    }
+
+    fn set_write_queue_depth(&mut self, depth: usize) {
+        match self {
+            Self::Spp(spp) => spp.set_write_queue_depth(depth),
+            Self::Ble(ble) => ble.set_write_queue_depth(depth),
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => mock.set_write_queue_depth(depth),
+        }
+    }
+
+    async fn start(
+        &mut self,
+        params: &ConnectParams,
+        addr: String,
+        callbacks: SessionCallbacks,
+    ) -> Result<DeviceConnectionInfo, DeviceError> {
+        let ct = connect_type_from_str(&params.connect_type);
+        match self {
+            Self::Spp(spp) => {
+                spp.start(
+                    params.name.clone(),
+                    addr,
+                    params.authkey.clone(),
+                    params.sar_version,
+                    ct,
+                    params.tx_win_overrun_allowance,
+                    callbacks,
+                )
+                .await
+            }
+            Self::Ble(ble) => {
+                ble.start(
+                    params.name.clone(),
+                    addr,
+                    params.authkey.clone(),
+                    params.sar_version,
+                    ct,
+                    params.tx_win_overrun_allowance,
+                    callbacks,
+                )
+                .await
+            }
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => {
+                mock.start(
+                    params.name.clone(),
+                    addr,
+                    params.authkey.clone(),
+                    params.sar_version,
+                    ct,
+                    params.tx_win_overrun_allowance,
+                    callbacks,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn disconnect(self) -> Result<(), JsValue> {
+        match self {
+            Self::Spp(spp) => spp.disconnect().await,
+            Self::Ble(ble) => ble.disconnect().await,
+            #[cfg(feature = "mock")]
+            Self::Mock(mock) => mock.disconnect().await,
+        }
+    }
 }
 
 pub(super) fn ensure_core_initialized() {
     CORE_INIT.get_or_init(|| {
-        console_error_panic_hook::set_once();
-        corelib::logger::wasm::init_logger();
+        events::install_panic_hook();
+        logging::init_logger();
         corelib::ecs::init_runtime_default();
     });
 }
 
-fn emit_event(event: &str, payload: &JsValue) {
-    EVENT_SINK.with(|cell| {
-        if let Some(ref sink) = *cell.borrow() {
-            if let Err(err) = sink.call2(&JsValue::NULL, &JsValue::from_str(event), payload) {
-                web_sys::console::error_2(&JsValue::from_str("emit_event failed"), &err);
-            }
+/// Outcome of one entry in a bulk operation.
+#[derive(Serialize)]
+pub(super) struct ItemResult {
+    pub id: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl ItemResult {
+    pub(super) fn new(id: String, result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self {
+                id,
+                ok: true,
+                error: None,
+            },
+            Err(err) => Self {
+                id,
+                ok: false,
+                error: Some(err),
+            },
         }
-    });
+    }
+}
+
+#[derive(Serialize)]
+struct StateChange<'a> {
+    addr: &'a str,
+    state: ConnectionState,
+}
+
+fn emit_connection_state(addr: &str, state: ConnectionState) {
+    #[cfg(astrobox_priv_cloned)]
+    debug::record_state(addr, state);
+    if let Ok(payload) = to_js_value(&StateChange { addr, state }) {
+        emit_event("connection-state-changed", addr, &payload);
+    }
 }
 
 fn connect_type_from_str(value: &str) -> ConnectType {
@@ -82,7 +299,16 @@ async fn remove_device_and_get_info(addr: &str) -> Option<DeviceConnectionInfo>
     }
 }
 
-async fn notify_disconnected(addr: String) {
+async fn notify_disconnected(addr: String, reason: DisconnectReason) {
+    keepalive::stop_keepalive(&addr);
+    idle::stop_idle_watch(&addr);
+    COMMAND_LOCKS.with(|cell| cell.borrow_mut().remove(&addr));
+    paging::forget(&addr, None);
+    watchface::forget_current(&addr);
+    install::forget_cancelled(&addr);
+    if reason == DisconnectReason::User {
+        reconnect::cancel(&addr);
+    }
     let info = remove_device_and_get_info(&addr)
         .await
         .unwrap_or(DeviceConnectionInfo {
@@ -90,46 +316,392 @@ async fn notify_disconnected(addr: String) {
             addr: addr.clone(),
             kind: DeviceKind::Xiaomi,
         });
+    let expected_after_update = firmware::take_expected_reboot(&addr);
+    emit_connection_state(&addr, ConnectionState::Disconnected);
     if let Ok(payload) = to_js_value(&info) {
-        emit_event("device-disconnected", &payload);
+        let _ = js_sys::Reflect::set(
+            &payload,
+            &JsValue::from_str("expected_after_update"),
+            &JsValue::from_bool(expected_after_update),
+        );
+        if let Ok(reason) = to_js_value(&reason) {
+            let _ = js_sys::Reflect::set(&payload, &JsValue::from_str("reason"), &reason);
+        }
+        emit_event("device-disconnected", &addr, &payload);
     }
 }
 
 async fn disconnect_all_sessions() {
     let sessions = SESSIONS.with(|cell| {
         let mut map = cell.borrow_mut();
-        map.drain().collect::<Vec<(String, XiaomiSpp)>>()
+        map.drain().collect::<Vec<(String, Session)>>()
     });
 
     for (addr, session) in sessions {
         let _ = session.disconnect().await;
-        notify_disconnected(addr).await;
+        notify_disconnected(addr, DisconnectReason::User).await;
     }
 }
 
-async fn handle_remote_disconnect(addr: String) {
-    SESSIONS.with(|cell| {
-        cell.borrow_mut().remove(&addr);
-    });
-    notify_disconnected(addr).await;
+async fn handle_remote_disconnect(addr: String, reason: DisconnectReason) {
+    // Both the read and write loops report a dead link; only the first one
+    // still finds the session and tears it down.
+    let removed = SESSIONS.with(|cell| cell.borrow_mut().remove(&addr));
+    let Some(session) = removed else {
+        return;
+    };
+    let _ = session.disconnect().await;
+    notify_disconnected(addr.clone(), reason).await;
+    reconnect::schedule(addr, reason);
+}
+
+#[derive(Serialize)]
+struct LinkError<'a> {
+    addr: &'a str,
+    direction: &'a str,
+    message: &'a str,
+}
+
+fn emit_link_error(addr: &str, direction: &str, message: &str) {
+    // The link going away is the expected end of a firmware update.
+    if firmware::reboot_expected(addr) {
+        return;
+    }
+    if let Ok(payload) = to_js_value(&LinkError {
+        addr,
+        direction,
+        message,
+    }) {
+        emit_event("link-error", addr, &payload);
+    }
 }
 
 pub(super) async fn await_result_receiver<T, E>(
     rx: oneshot::Receiver<Result<T, E>>,
     missing_msg: &'static str,
-) -> Result<T, JsValue>
+) -> Result<T, DeviceError>
 where
     E: std::fmt::Display,
 {
-    let result = rx.await.map_err(|_| JsValue::from_str(missing_msg))?;
-    result.map_err(|err| JsValue::from_str(&err.to_string()))
+    let result = rx
+        .await
+        .map_err(|_| DeviceError::new(ErrorCode::Internal, missing_msg))?;
+    result.map_err(DeviceError::from_core)
 }
 
+pub(super) const DEFAULT_REQUEST_TIMEOUT_MS: u32 = 10_000;
+const DEFAULT_CONNECT_TIMEOUT_MS: u32 = 20_000;
+const DEFAULT_REQUEST_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY_MS: u32 = 200;
+const DEFAULT_READY_TIMEOUT_MS: u32 = 10_000;
+const READY_PROBE_INTERVAL_MS: u32 = 250;
+
+pub(super) async fn await_result_receiver_timeout<T, E>(
+    rx: oneshot::Receiver<Result<T, E>>,
+    missing_msg: &'static str,
+    timeout_ms: u32,
+) -> Result<T, DeviceError>
+where
+    E: std::fmt::Display,
+{
+    tokio::select! {
+        biased;
+        result = await_result_receiver(rx, missing_msg) => result,
+        _ = sleep_ms(timeout_ms) => Err(DeviceError::new(
+            ErrorCode::Timeout,
+            format!("{missing_msg} within {timeout_ms}ms"),
+        )),
+    }
+}
+
+/// Sends an info request, retrying recoverable failures such as timeouts
+/// with exponential backoff. Anything else, e.g. a missing device or a
+/// rejected auth, fails on the first attempt.
+async fn request_info_with_retry<T, E>(
+    addr: &str,
+    request: fn(&mut InfoSystem) -> oneshot::Receiver<Result<T, E>>,
+    missing_msg: &'static str,
+) -> Result<T, DeviceError>
+where
+    T: Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    let attempts = REQUEST_ATTEMPTS.with(Cell::get).max(1);
+    let mut delay_ms = RETRY_BASE_DELAY_MS;
+    let mut attempt = 1;
+    loop {
+        let result = async {
+            let rx = with_info_system(addr, move |sys| Ok(request(sys)))
+                .await
+                .map_err(DeviceError::from_core)?;
+            await_result_receiver_timeout(rx, missing_msg, DEFAULT_REQUEST_TIMEOUT_MS).await
+        }
+        .await;
+        match result {
+            Err(err) if err.recoverable && attempt < attempts => {
+                log::debug!(
+                    "[wasm] Request to {} failed ({}/{}), retrying in {}ms: {}",
+                    addr,
+                    attempt,
+                    attempts,
+                    delay_ms,
+                    err
+                );
+                sleep_ms(delay_ms).await;
+                delay_ms = delay_ms.saturating_mul(2);
+                attempt += 1;
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Sets how many times `device_get_data` tries an info, status or storage
+/// request before giving up, 3 by default. 1 disables retries.
+#[wasm_bindgen]
+pub fn device_set_request_attempts(attempts: u32) {
+    REQUEST_ATTEMPTS.with(|cell| cell.set(attempts.max(1)));
+}
+
+/// Caps how many devices may be connected at once; 0 removes the cap, which
+/// is the default. Existing sessions are kept if they already exceed it.
 #[wasm_bindgen]
-pub fn register_event_sink(callback: js_sys::Function) {
-    EVENT_SINK.with(|cell| {
-        *cell.borrow_mut() = Some(callback);
+pub fn device_set_max_sessions(max: u32) {
+    MAX_SESSIONS.with(|cell| cell.set((max > 0).then_some(max as usize)));
+}
+
+/// Polls the device status until it answers; right after auth some devices
+/// drop requests for a while.
+async fn wait_until_ready(addr: &str, timeout_ms: u32) -> Result<(), DeviceError> {
+    let probe = async {
+        loop {
+            let answered = match with_info_system(addr, |sys| Ok(sys.request_device_status())).await
+            {
+                Ok(rx) => await_result_receiver_timeout(
+                    rx,
+                    "Device status response not received",
+                    READY_PROBE_INTERVAL_MS * 4,
+                )
+                .await
+                .is_ok(),
+                Err(_) => false,
+            };
+            if answered {
+                return;
+            }
+            sleep_ms(READY_PROBE_INTERVAL_MS).await;
+        }
+    };
+    tokio::select! {
+        biased;
+        _ = probe => Ok(()),
+        _ = sleep_ms(timeout_ms) => Err(DeviceError::new(
+            ErrorCode::ConnectTimeout,
+            format!("Device did not answer a status request within {timeout_ms}ms"),
+        )),
+    }
+}
+
+/// How long connecting waits for the serial number before going without it.
+const DEVICE_ID_TIMEOUT_MS: u32 = 3_000;
+
+/// Asks the device for its serial number, which unlike the transport address
+/// stays the same across reconnects. The connect call and `device-connected`
+/// wait for it, so a device that doesn't answer is given up on quickly.
+async fn query_device_id(addr: &str) -> Option<String> {
+    let rx = with_info_system(addr, |sys| Ok(sys.request_device_info()))
+        .await
+        .ok()?;
+    let info = await_result_receiver_timeout(
+        rx,
+        "Device info response not received",
+        DEVICE_ID_TIMEOUT_MS,
+    )
+    .await
+    .ok()?;
+    Some(info.serial_number).filter(|id| !id.is_empty())
+}
+
+/// Rejects with `TooManySessions` when `device_set_max_sessions` is reached.
+/// A session replacing one for the same address doesn't count against the
+/// cap; an empty `addr` is only known after the picker, so it counts in full.
+fn check_session_cap(addr: &str) -> Result<(), DeviceError> {
+    let others = SESSIONS.with(|cell| {
+        let sessions = cell.borrow();
+        sessions.len() - usize::from(sessions.contains_key(addr))
     });
+    match MAX_SESSIONS.with(Cell::get) {
+        Some(max) if others >= max => Err(DeviceError::new(
+            ErrorCode::TooManySessions,
+            format!("Already connected to {others} devices, the limit is {max}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn start_session(
+    mut session: Session,
+    addr: String,
+    mut params: ConnectParams,
+) -> Result<JsValue, JsValue> {
+    let target = if addr.trim().is_empty() {
+        session.device_addr().to_string()
+    } else {
+        addr.clone()
+    };
+    // Checked again now that the address is known, as another connect may
+    // have finished while this one was opening its transport.
+    if let Err(err) = check_session_cap(&target) {
+        let _ = session.disconnect().await;
+        return Err(err.into());
+    }
+    let stale = SESSIONS.with(|cell| cell.borrow_mut().remove(&target));
+    if let Some(stale) = stale {
+        let _ = stale.disconnect().await;
+        notify_disconnected(target.clone(), DisconnectReason::Reconnecting).await;
+    }
+
+    session.set_write_queue_depth(resolve_write_queue_depth(params.options.write_queue_depth)?);
+    let display_name = params
+        .options
+        .display_name
+        .clone()
+        .filter(|name| !name.trim().is_empty());
+    let has_display_name = display_name.is_some();
+    if let Some(display_name) = display_name {
+        params.name = display_name;
+    }
+    if let (Session::Spp(spp), Some(retries)) = (&mut session, params.options.read_retries) {
+        spp.set_read_retries(retries);
+    }
+
+    let callbacks = SessionCallbacks {
+        on_disconnect: Rc::new(|target, reason| {
+            spawn_local(async move {
+                handle_remote_disconnect(target, reason).await;
+            });
+        }),
+        on_state: Rc::new(emit_connection_state),
+        on_link_error: Rc::new(emit_link_error),
+        on_inbound: Some(Rc::new(|addr, _data| {
+            idle::touch_inbound(addr);
+            #[cfg(astrobox_priv_cloned)]
+            {
+                debug::record_inbound(addr);
+                raw::capture_inbound(addr, _data);
+            }
+        })),
+        #[cfg(astrobox_priv_cloned)]
+        on_outbound: Some(Rc::new(raw::intercept_outbound)),
+        #[cfg(not(astrobox_priv_cloned))]
+        on_outbound: None,
+    };
+
+    let timeout_ms = params
+        .options
+        .timeout_ms
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS);
+    let started = tokio::select! {
+        biased;
+        res = session.start(&params, addr, callbacks) => Some(res),
+        _ = sleep_ms(timeout_ms) => None,
+    };
+    let device_info = match started {
+        Some(res) => res?,
+        None => {
+            let _ = session.disconnect().await;
+            remove_device_and_get_info(&target).await;
+            emit_connection_state(&target, ConnectionState::Error);
+            return Err(DeviceError::new(
+                ErrorCode::ConnectTimeout,
+                format!("Connection not established within {timeout_ms}ms"),
+            )
+            .into());
+        }
+    };
+    let keepalive_interval = params.options.keepalive_interval_ms.unwrap_or(0);
+    let keepalive_max_failures = params
+        .options
+        .keepalive_max_failures
+        .unwrap_or(keepalive::DEFAULT_KEEPALIVE_MAX_FAILURES);
+    let idle_timeout_ms = params.options.idle_timeout_ms;
+    let ready_timeout_ms = params.options.wait_ready.then(|| {
+        params
+            .options
+            .ready_timeout_ms
+            .unwrap_or(DEFAULT_READY_TIMEOUT_MS)
+    });
+
+    #[cfg(astrobox_priv_cloned)]
+    debug::record_session(&device_info.addr, &params.connect_type, params.sar_version);
+
+    if let Session::Spp(spp) = &session {
+        let known = KnownPort {
+            port: spp.port().clone(),
+            identity: spp.identity().clone(),
+            params,
+        };
+        KNOWN_PORTS.with(|cell| {
+            cell.borrow_mut().insert(device_info.addr.clone(), known);
+        });
+    }
+    let transport_label = session
+        .device_label()
+        .map_or(JsValue::NULL, JsValue::from_str);
+    if has_display_name {
+        LABELS.with(|cell| cell.borrow_mut().remove(&device_info.addr));
+    }
+    SESSIONS.with(|cell| {
+        cell.borrow_mut().insert(device_info.addr.clone(), session);
+    });
+    keepalive::start_keepalive(
+        device_info.addr.clone(),
+        keepalive_interval,
+        keepalive_max_failures,
+    );
+    if let Some(idle_timeout) = idle_timeout_ms.filter(|ms| *ms > 0) {
+        idle::start_idle_watch(device_info.addr.clone(), idle_timeout);
+    }
+
+    if let Some(timeout_ms) = ready_timeout_ms
+        && let Err(err) = wait_until_ready(&device_info.addr, timeout_ms).await
+    {
+        let removed = SESSIONS.with(|cell| cell.borrow_mut().remove(&device_info.addr));
+        if let Some(session) = removed {
+            let _ = session.disconnect().await;
+        }
+        notify_disconnected(device_info.addr.clone(), DisconnectReason::Timeout).await;
+        return Err(err.into());
+    }
+
+    let payload =
+        to_js_value(&device_info).map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
+    let device_id = query_device_id(&device_info.addr)
+        .await
+        .map_or(JsValue::NULL, |id| JsValue::from_str(&id));
+    let _ = js_sys::Reflect::set(&payload, &JsValue::from_str("device_id"), &device_id);
+    let _ = js_sys::Reflect::set(
+        &payload,
+        &JsValue::from_str("transport_label"),
+        &transport_label,
+    );
+    emit_event("device-connected", &device_info.addr, &payload);
+    Ok(payload)
+}
+
+fn parse_connect_options(options: JsValue) -> Result<ConnectOptions, DeviceError> {
+    let options = from_js_value::<Option<ConnectOptions>>(options)
+        .map_err(|err| {
+            DeviceError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid connect options: {}", err),
+            )
+        })?
+        .unwrap_or_default();
+    resolve_baud_rate(options.baud_rate)?;
+    resolve_buffer_size(options.buffer_size)?;
+    resolve_write_queue_depth(options.write_queue_depth)?;
+    Ok(options)
 }
 
 #[wasm_bindgen]
@@ -140,39 +712,204 @@ pub async fn device_connect(
     sar_version: u32,
     connect_type: String,
     tx_win_overrun_allowance: u8,
+    options: JsValue,
 ) -> Result<JsValue, JsValue> {
     ensure_core_initialized();
 
-    disconnect_all_sessions().await;
+    let options = parse_connect_options(options)?;
+    check_session_cap(&addr)?;
+    let session = match connect_type_from_str(&connect_type) {
+        ConnectType::BLE => Session::Ble(XiaomiBle::new().await?),
+        _ => Session::Spp(
+            XiaomiSpp::new(options.baud_rate, options.buffer_size, &options.filters).await?,
+        ),
+    };
+    let params = ConnectParams {
+        name,
+        authkey,
+        sar_version,
+        connect_type,
+        tx_win_overrun_allowance,
+        options,
+    };
+    start_session(session, addr, params).await
+}
 
-    let mut session = XiaomiSpp::new(None).await?;
-    let ct = connect_type_from_str(&connect_type);
-    let disconnect_cb: Rc<dyn Fn(String)> = Rc::new(|target| {
-        spawn_local(async move {
-            handle_remote_disconnect(target).await;
-        });
-    });
+#[derive(Serialize)]
+pub(super) struct GrantedPort {
+    pub port_id: u32,
+    pub serial_number: Option<String>,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+}
 
-    let device_info = session
-        .start(
-            name,
-            addr,
-            authkey,
-            sar_version,
-            ct,
-            tx_win_overrun_allowance,
-            disconnect_cb,
-        )
-        .await?;
+pub(super) async fn list_granted_ports() -> Result<Vec<GrantedPort>, DeviceError> {
+    let ports = granted_ports().await?;
+    Ok(GRANTED_PORTS.with(|cell| {
+        let mut granted = cell.borrow_mut();
+        granted.retain(|(_, known)| ports.iter().any(|port| port == known));
+        ports
+            .into_iter()
+            .map(|port| {
+                let port_id = match granted.iter().find(|(_, known)| *known == port) {
+                    Some((id, _)) => *id,
+                    None => {
+                        let id = NEXT_PORT_ID.with(|next| {
+                            let id = next.get();
+                            next.set(id.wrapping_add(1));
+                            id
+                        });
+                        granted.push((id, port.clone()));
+                        id
+                    }
+                };
+                let identity = PortIdentity::of(&port);
+                GrantedPort {
+                    port_id,
+                    serial_number: identity.serial_number,
+                    vendor_id: identity.vendor_id,
+                    product_id: identity.product_id,
+                }
+            })
+            .collect::<Vec<_>>()
+    }))
+}
 
-    SESSIONS.with(|cell| {
-        cell.borrow_mut().insert(device_info.addr.clone(), session);
+/// Lists the serial ports this origin was already granted. `port_id` stays
+/// the same for a port across calls and is what `device_connect_known` takes.
+#[wasm_bindgen]
+pub async fn device_list_known_ports() -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let listed = list_granted_ports().await?;
+    to_js_value(&listed).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// Connects over a port from `device_list_known_ports` without the picker.
+#[wasm_bindgen]
+pub async fn device_connect_known(
+    port_id: u32,
+    name: String,
+    addr: String,
+    authkey: String,
+    sar_version: u32,
+    tx_win_overrun_allowance: u8,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let options = parse_connect_options(options)?;
+    check_session_cap(&addr)?;
+    let port = GRANTED_PORTS.with(|cell| {
+        cell.borrow()
+            .iter()
+            .find(|(id, _)| *id == port_id)
+            .map(|(_, port)| port.clone())
     });
+    let Some(port) = port else {
+        return Err(DeviceError::new(
+            ErrorCode::PortNotCached,
+            format!("Unknown port id {port_id}, call device_list_known_ports first"),
+        )
+        .into());
+    };
 
-    let payload =
-        to_js_value(&device_info).map_err(|err| JsValue::from_str(&format!("{:?}", err)))?;
-    emit_event("device-connected", &payload);
-    Ok(payload)
+    let session = XiaomiSpp::from_port(port, options.baud_rate, options.buffer_size).await?;
+    let params = ConnectParams {
+        name,
+        authkey,
+        sar_version,
+        connect_type: "SPP".to_string(),
+        tx_win_overrun_allowance,
+        options,
+    };
+    start_session(Session::Spp(session), addr, params).await
+}
+
+/// Connects over a `SerialPort` the caller already holds, e.g. one handed out
+/// by a test harness or a virtual serial device, without the picker.
+#[wasm_bindgen]
+pub async fn device_connect_port(
+    port: SerialPort,
+    name: String,
+    addr: String,
+    authkey: String,
+    sar_version: u32,
+    tx_win_overrun_allowance: u8,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let options = parse_connect_options(options)?;
+    check_session_cap(&addr)?;
+    let session = XiaomiSpp::from_port(port, options.baud_rate, options.buffer_size).await?;
+    let params = ConnectParams {
+        name,
+        authkey,
+        sar_version,
+        connect_type: "SPP".to_string(),
+        tx_win_overrun_allowance,
+        options,
+    };
+    start_session(Session::Spp(session), addr, params).await
+}
+
+/// Connects to an in-memory device that answers from `script`:
+/// `{ addr?, label?, steps: [{ expect?, reply: [], delay_ms? }] }` with frames
+/// as hex strings. Only built with the `mock` feature.
+#[cfg(feature = "mock")]
+#[wasm_bindgen]
+pub async fn device_connect_mock(
+    name: String,
+    addr: String,
+    authkey: String,
+    sar_version: u32,
+    script: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let options = parse_connect_options(options)?;
+    check_session_cap(&addr)?;
+    let session = XiaomiMock::new(script)?;
+    let params = ConnectParams {
+        name,
+        authkey,
+        sar_version,
+        connect_type: "SPP".to_string(),
+        tx_win_overrun_allowance: 0,
+        options,
+    };
+    start_session(Session::Mock(session), addr, params).await
+}
+
+/// Reconnects to a previously connected device using its cached serial port,
+/// so the user is not prompted by the port picker again.
+#[wasm_bindgen]
+pub async fn device_reconnect(addr: String) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    reconnect_known(addr).await
+}
+
+pub(super) fn has_known_port(addr: &str) -> bool {
+    KNOWN_PORTS.with(|cell| cell.borrow().contains_key(addr))
+}
+
+async fn reconnect_known(addr: String) -> Result<JsValue, JsValue> {
+    let known = KNOWN_PORTS.with(|cell| cell.borrow().get(&addr).cloned());
+    let Some(known) = known else {
+        return Err(DeviceError::new(
+            ErrorCode::PortNotCached,
+            format!("No cached serial port for {addr}, use device_connect instead"),
+        )
+        .into());
+    };
+    check_session_cap(&addr)?;
+
+    let session = XiaomiSpp::reopen(
+        &known.port,
+        &known.identity,
+        known.params.options.baud_rate,
+        known.params.options.buffer_size,
+    )
+    .await?;
+    start_session(Session::Spp(session), addr, known.params).await
 }
 
 #[wasm_bindgen]
@@ -182,14 +919,183 @@ pub async fn device_disconnect(addr: String) -> Result<(), JsValue> {
     if let Some(session) = removed {
         let _ = session.disconnect().await;
     }
-    notify_disconnected(addr).await;
+    notify_disconnected(addr, DisconnectReason::User).await;
+    Ok(())
+}
+
+/// Serial control lines for `device_set_signals`; unset lines are left as they are.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+struct SignalOptions {
+    dtr: Option<bool>,
+    rts: Option<bool>,
+    brk: Option<bool>,
+}
+
+/// Sets DTR, RTS or break on the serial port behind `addr`, which some
+/// devices read to enter their bootloader or recovery mode.
+#[wasm_bindgen]
+pub async fn device_set_signals(addr: String, signals: JsValue) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    let options = from_js_value::<SignalOptions>(signals).map_err(|err| {
+        DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Invalid signals: {}", err),
+        )
+    })?;
+    let port = SESSIONS.with(|cell| match cell.borrow().get(&addr) {
+        Some(Session::Spp(spp)) => Ok(spp.port().clone()),
+        Some(_) => Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            "Control signals are only available on serial connections",
+        )),
+        None => Err(DeviceError::new(
+            ErrorCode::DeviceNotFound,
+            "Device not found",
+        )),
+    })?;
+
+    let output = SerialOutputSignals::new();
+    if let Some(dtr) = options.dtr {
+        output.set_data_terminal_ready(dtr);
+    }
+    if let Some(rts) = options.rts {
+        output.set_request_to_send(rts);
+    }
+    if let Some(brk) = options.brk {
+        output.set_break(brk);
+    }
+    wasm_bindgen_futures::JsFuture::from(port.set_signals_with_signals(&output))
+        .await
+        .map_err(|err| DeviceError::from_js(ErrorCode::Internal, &err))?;
+    Ok(())
+}
+
+/// Renames a device for this module's lifetime. corelib fixes the entity name
+/// at `create_device`, so the label is kept here and applied on top of it in
+/// `device_get_connected_devices` and `device-updated` events. The transport
+/// label reported next to it is left alone.
+#[wasm_bindgen]
+pub fn device_set_label(addr: String, label: String) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    if !SESSIONS.with(|cell| cell.borrow().contains_key(&addr)) {
+        return Err(DeviceError::new(ErrorCode::DeviceNotFound, "Device not found").into());
+    }
+    LABELS.with(|cell| {
+        cell.borrow_mut().insert(addr.clone(), label.clone());
+    });
+
+    let info = DeviceConnectionInfo {
+        name: label,
+        addr: addr.clone(),
+        kind: DeviceKind::Xiaomi,
+    };
+    if let Ok(payload) = to_js_value(&info) {
+        emit_event("device-updated", &addr, &payload);
+    }
     Ok(())
 }
 
+/// Drops the session and reports it disconnected right away, closing the
+/// transport in the background in case the port itself is wedged.
+#[wasm_bindgen]
+pub async fn device_force_disconnect(addr: String) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    let removed = SESSIONS.with(|cell| cell.borrow_mut().remove(&addr));
+    notify_disconnected(addr, DisconnectReason::User).await;
+    if let Some(session) = removed {
+        spawn_local(async move {
+            let _ = session.disconnect().await;
+        });
+    }
+    Ok(())
+}
+
+/// Resolves once everything queued for `addr` before this call has been
+/// handed to the port.
+#[wasm_bindgen]
+pub async fn device_flush(addr: String) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    flush_writes(&addr).await.map_err(JsValue::from)
+}
+
+pub(super) async fn flush_writes(addr: &str) -> Result<(), DeviceError> {
+    let sender = SESSIONS
+        .with(|cell| cell.borrow().get(addr).and_then(|s| s.write_sender()))
+        .ok_or_else(|| DeviceError::new(ErrorCode::DeviceNotFound, "Device not found"))?;
+    let (ack_tx, ack_rx) = oneshot::channel();
+    let closed = || DeviceError::new(ErrorCode::DeviceNotFound, "Writer closed before flushing");
+    sender
+        .send(WriteCommand::Flush(ack_tx))
+        .await
+        .map_err(|_| closed())?;
+    ack_rx.await.map_err(|_| closed())
+}
+
+/// Reports whether `addr` has a live transport session, without going
+/// through the ECS runtime.
+#[wasm_bindgen]
+pub fn device_is_connected(addr: String) -> bool {
+    SESSIONS.with(|cell| cell.borrow().contains_key(&addr))
+}
+
+/// `None` when `addr` has no session, otherwise whether it runs over BLE.
+pub(super) fn session_is_ble(addr: &str) -> Option<bool> {
+    SESSIONS.with(|cell| {
+        cell.borrow()
+            .get(addr)
+            .map(|session| matches!(session, Session::Ble(_)))
+    })
+}
+
+#[wasm_bindgen]
+pub async fn device_disconnect_all() -> Result<(), JsValue> {
+    ensure_core_initialized();
+    disconnect_all_sessions().await;
+    Ok(())
+}
+
+/// Disconnects every session on `pagehide` so ports are released when the
+/// tab is closed, reloaded or navigated away from. Hiding the tab only fires
+/// `visibilitychange`, which leaves sessions open. Calling it again keeps the
+/// one listener.
+#[wasm_bindgen]
+pub fn device_install_unload_handler() -> Result<(), JsValue> {
+    if UNLOAD_HANDLER.with(|cell| cell.borrow().is_some()) {
+        return Ok(());
+    }
+    let handler = EventHandler::new(|_event: JsValue| {
+        spawn_local(disconnect_all_sessions());
+    });
+    web_sys::window()
+        .ok_or_else(|| DeviceError::new(ErrorCode::Internal, "No window to listen on"))?
+        .add_event_listener_with_callback("pagehide", handler.as_ref().unchecked_ref())?;
+    UNLOAD_HANDLER.with(|cell| *cell.borrow_mut() = Some(handler));
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn device_remove_unload_handler() {
+    let Some(handler) = UNLOAD_HANDLER.with(|cell| cell.borrow_mut().take()) else {
+        return;
+    };
+    if let Some(window) = web_sys::window() {
+        let _ = window
+            .remove_event_listener_with_callback("pagehide", handler.as_ref().unchecked_ref());
+    }
+}
+
+#[derive(Serialize)]
+struct ConnectedDevice {
+    #[serde(flatten)]
+    info: DeviceConnectionInfo,
+    transport_label: Option<String>,
+}
+
 #[wasm_bindgen]
 pub async fn device_get_connected_devices() -> Result<JsValue, JsValue> {
     ensure_core_initialized();
-    let devices = corelib::ecs::with_rt_mut(|rt| {
+    let mut devices = corelib::ecs::with_rt_mut(|rt| {
         rt.device_ids()
             .filter_map(|device_id| {
                 rt.component_ref::<Device>(device_id)
@@ -202,11 +1108,107 @@ pub async fn device_get_connected_devices() -> Result<JsValue, JsValue> {
             .collect::<Vec<_>>()
     })
     .await;
+    for device in &mut devices {
+        if let Some(label) = LABELS.with(|cell| cell.borrow().get(&device.addr).cloned()) {
+            device.name = label;
+        }
+    }
+    let devices = devices
+        .into_iter()
+        .map(|info| {
+            let transport_label = SESSIONS.with(|cell| {
+                cell.borrow()
+                    .get(&info.addr)
+                    .and_then(Session::device_label)
+                    .map(str::to_string)
+            });
+            ConnectedDevice {
+                info,
+                transport_label,
+            }
+        })
+        .collect::<Vec<_>>();
 
     to_js_value(&devices).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
 }
 
+/// Drops what is cached for `addr` so the next lookup asks the device again:
+/// the quick app list behind package/fingerprint lookups and its pages for
+/// `apps`, the tracked current face and the watchface pages for
+/// `watchfaces`, or everything when `kind` is omitted or `all`.
+#[wasm_bindgen]
+pub async fn device_invalidate_cache(addr: String, kind: Option<String>) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    let (apps, watchfaces) = match kind.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("all") => (true, true),
+        Some("apps") => (true, false),
+        Some("watchfaces") => (false, true),
+        Some(other) => {
+            return Err(DeviceError::new(
+                ErrorCode::InvalidArgument,
+                format!("Unknown cache kind: {other}"),
+            )
+            .into());
+        }
+    };
+
+    if apps {
+        let owned = addr.clone();
+        corelib::ecs::with_rt_mut(move |rt| {
+            rt.with_device_mut(&owned, |world, entity| {
+                if let Some(mut component) = world.get_mut::<ResourceComponent>(entity) {
+                    component.quick_apps.clear();
+                }
+            })
+        })
+        .await
+        .ok_or_else(|| DeviceError::new(ErrorCode::DeviceNotFound, "Device not found"))?;
+        paging::forget(&addr, Some("quick_apps"));
+    }
+    if watchfaces {
+        watchface::forget_current(&addr);
+        paging::forget(&addr, Some("watchfaces"));
+    }
+    Ok(())
+}
+
+/// Per-device queue for mutating commands. tokio's mutex wakes waiters in
+/// FIFO order, so commands reach the device in submission order instead of
+/// interleaving their packets on the wire.
+pub(super) fn command_lock(addr: &str) -> Rc<Mutex<()>> {
+    COMMAND_LOCKS.with(|cell| {
+        cell.borrow_mut()
+            .entry(addr.to_string())
+            .or_insert_with(|| Rc::new(Mutex::new(())))
+            .clone()
+    })
+}
+
+/// Runs a mutating `command` on `addr` once the commands submitted before it
+/// are done. The systems only queue their packets, so the queue is held
+/// until `command` has resolved and its writes have been handed to the port.
+pub(super) async fn serialized<T>(addr: &str, command: impl Future<Output = T>) -> T {
+    let lock = command_lock(addr);
+    let _queued = lock.lock().await;
+    let output = command.await;
+    if let Err(err) = flush_writes(addr).await {
+        log::debug!("[wasm] No flush after command on {}: {}", addr, err);
+    }
+    output
+}
+
 pub(super) async fn with_info_system<F, R>(addr: &str, f: F) -> Result<R, String>
+where
+    F: FnOnce(&mut InfoSystem) -> Result<R, String> + Send + 'static,
+    R: Send + 'static,
+{
+    idle::touch(addr);
+    poll_info_system(addr, f).await
+}
+
+/// `with_info_system` for the requests of `idle::probe`, which don't count
+/// as activity.
+pub(super) async fn poll_info_system<F, R>(addr: &str, f: F) -> Result<R, String>
 where
     F: FnOnce(&mut InfoSystem) -> Result<R, String> + Send + 'static,
     R: Send + 'static,
@@ -229,6 +1231,7 @@ where
     F: FnOnce(&mut InstallSystem) -> Result<R, String> + 'static,
     R: 'static,
 {
+    idle::touch(addr);
     let owned = addr.to_string();
     corelib::ecs::with_rt_mut(move |rt| {
         rt.with_device_mut(&owned, |world, entity| {
@@ -247,6 +1250,7 @@ where
     F: FnOnce(&mut ResourceSystem) -> Result<R, String> + Send + 'static,
     R: Send + 'static,
 {
+    idle::touch(addr);
     let owned = addr.to_string();
     corelib::ecs::with_rt_mut(move |rt| {
         rt.with_device_mut(&owned, |world, entity| {
@@ -265,6 +1269,7 @@ where
     F: FnOnce(&ResourceComponent) -> Result<R, String> + Send + 'static,
     R: Send + 'static,
 {
+    idle::touch(addr);
     let owned = addr.to_string();
     corelib::ecs::with_rt_mut(move |rt| {
         rt.with_device_mut(&owned, |world, entity| {
@@ -283,6 +1288,7 @@ where
     F: FnOnce(&mut WatchfaceSystem) -> Result<R, String> + Send + 'static,
     R: Send + 'static,
 {
+    idle::touch(addr);
     let owned = addr.to_string();
     corelib::ecs::with_rt_mut(move |rt| {
         rt.with_device_mut(&owned, |world, entity| {
@@ -301,6 +1307,7 @@ where
     F: FnOnce(&mut ThirdpartyAppSystem) -> Result<R, String> + Send + 'static,
     R: Send + 'static,
 {
+    idle::touch(addr);
     let owned = addr.to_string();
     corelib::ecs::with_rt_mut(move |rt| {
         rt.with_device_mut(&owned, |world, entity| {
@@ -314,115 +1321,129 @@ where
     .await
 }
 
+/// Removes the `abort` listener of `aborted` when its future settles or is
+/// dropped, so a long-lived signal doesn't collect one per request.
+struct AbortListener {
+    signal: AbortSignal,
+    callback: Option<js_sys::Function>,
+}
+
+impl Drop for AbortListener {
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            let _ = self
+                .signal
+                .remove_event_listener_with_callback("abort", &callback);
+        }
+    }
+}
+
+/// Resolves once `signal` fires, immediately if it already has.
+async fn aborted(signal: &AbortSignal) {
+    if signal.aborted() {
+        return;
+    }
+    let mut listener = AbortListener {
+        signal: signal.clone(),
+        callback: None,
+    };
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let _ = signal.add_event_listener_with_callback("abort", &resolve);
+        listener.callback = Some(resolve);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Fetches `info`, `status`, `battery`, `storage` or `storage_detail`. Aborting
+/// `signal` rejects with `Cancelled` and drops the pending request, so a late
+/// reply from the device is discarded.
 #[wasm_bindgen]
-pub async fn device_get_data(addr: String, data_type: String) -> Result<JsValue, JsValue> {
+pub async fn device_get_data(
+    addr: String,
+    data_type: String,
+    signal: Option<AbortSignal>,
+) -> Result<JsValue, JsValue> {
     ensure_core_initialized();
+    let Some(signal) = signal else {
+        return get_data(addr, data_type).await;
+    };
+    tokio::select! {
+        biased;
+        _ = aborted(&signal) => {
+            Err(DeviceError::new(ErrorCode::Cancelled, "Request cancelled").into())
+        }
+        res = get_data(addr, data_type) => res,
+    }
+}
+
+async fn get_data(addr: String, data_type: String) -> Result<JsValue, JsValue> {
     let lower = data_type.to_ascii_lowercase();
     match lower.as_str() {
         "info" => {
-            let rx = with_info_system(&addr, |sys| Ok(sys.request_device_info()))
-                .await
-                .map_err(|err| JsValue::from_str(&err))?;
-            let info = await_result_receiver(rx, "Device info response not received").await?;
+            let info = request_info_with_retry(
+                &addr,
+                InfoSystem::request_device_info,
+                "Device info response not received",
+            )
+            .await?;
             to_js_value(&info).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
         }
         "status" => {
-            let rx = with_info_system(&addr, |sys| Ok(sys.request_device_status()))
-                .await
-                .map_err(|err| JsValue::from_str(&err))?;
-            let status = await_result_receiver(rx, "Device status response not received").await?;
+            let status = request_info_with_retry(
+                &addr,
+                InfoSystem::request_device_status,
+                "Device status response not received",
+            )
+            .await?;
             to_js_value(&status).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
         }
+        "battery" => {
+            let status = request_info_with_retry(
+                &addr,
+                InfoSystem::request_device_status,
+                "Device status response not received",
+            )
+            .await?;
+            let battery = battery::battery_from_status(&status).ok_or_else(|| {
+                DeviceError::new(
+                    ErrorCode::Unsupported,
+                    "Battery level not reported by device",
+                )
+            })?;
+            to_js_value(&battery).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+        }
         "storage" => {
-            let rx = with_info_system(&addr, |sys| Ok(sys.request_device_storage()))
-                .await
-                .map_err(|err| JsValue::from_str(&err))?;
-            let storage = await_result_receiver(rx, "Device storage response not received").await?;
+            let storage = request_info_with_retry(
+                &addr,
+                InfoSystem::request_device_storage,
+                "Device storage response not received",
+            )
+            .await?;
             to_js_value(&storage).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
         }
-        other => Err(JsValue::from_str(&format!(
-            "Unsupported data type: {other}"
-        ))),
+        "storage_detail" => {
+            let detail = storage::storage_detail(&addr).await?;
+            to_js_value(&detail).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+        }
+        other => Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unsupported data type: {other}"),
+        )
+        .into()),
     }
 }
 
 #[wasm_bindgen]
-pub async fn device_install(
-    addr: String,
-    res_type: u8,
-    data: Uint8Array,
-    package_name: Option<String>,
-    progress_cb: Option<Function>,
-    watchface_id: Option<String>,
-) -> Result<(), JsValue> {
-    ensure_core_initialized();
-
-    let data_type = MassDataType::try_from(res_type).map_err(|err| JsValue::from_str(err))?;
-    let file_data = data.to_vec();
-
-    let (progress_tx, progress_rx) = unbounded::<SendMassCallbackData>();
-    let progress_notifier = {
-        let sender = progress_tx.clone();
-        Arc::new(move |payload: SendMassCallbackData| {
-            let _ = sender.try_send(payload);
-        }) as Arc<dyn Fn(SendMassCallbackData) + Send + Sync>
-    };
-
-    let package_name_clone = package_name.clone();
-    let install_future = with_install_system(&addr, move |install_sys| {
-        install_sys
-            .send_install_request_with_progress(
-                data_type,
-                file_data,
-                package_name_clone.as_deref(),
-                progress_notifier,
-                watchface_id.as_deref(),
-            )
-            .map_err(|err| format!("{:?}", err))
-    })
-    .await
-    .map_err(|err| JsValue::from_str(&err))?;
-
-    if let Some(callback) = progress_cb.clone() {
-        spawn_local(async move {
-            let receiver = progress_rx;
-            while let Ok(payload) = receiver.recv().await {
-                match to_js_value(&payload) {
-                    Ok(js_payload) => {
-                        let _ = callback.call1(&JsValue::NULL, &js_payload);
-                    }
-                    Err(err) => {
-                        web_sys::console::error_1(&JsValue::from_str(&format!(
-                            "[wasm] device_install progress serialization failed: {}",
-                            err
-                        )));
-                    }
-                }
-            }
-        });
-    } else {
-        drop(progress_rx);
-    }
-
-    let result = install_future
-        .await
-        .map_err(|err| JsValue::from_str(&format!("{:?}", err)));
-
-    drop(progress_tx);
-    result
+pub async fn device_get_file_type(file: Uint8Array, name: String) -> u8 {
+    detect_file_type(&file.to_vec(), &name) as u8
 }
 
-#[wasm_bindgen]
-pub async fn device_get_file_type(file: Uint8Array, name: String) -> u8 {
-    let file_type = get_file_type(&file.to_vec());
-    if file_type == FileType::Zip {
-        // 检查扩展名 abp
-        if let Some(ext) = name.split('.').last() {
-            if ext == "abp" {
-                return FileType::Abp as u8;
-            }
-        }
+pub(super) fn detect_file_type(data: &[u8], name: &str) -> FileType {
+    let file_type = get_file_type(data);
+    // 检查扩展名 abp
+    if file_type == FileType::Zip && name.rsplit('.').next() == Some("abp") {
+        return FileType::Abp;
     }
 
-    file_type as u8
+    file_type
 }
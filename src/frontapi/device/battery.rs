@@ -0,0 +1,134 @@
+use corelib::device::xiaomi::components::info::DeviceStatus;
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::{cell::Cell, cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use super::{
+    DEFAULT_REQUEST_TIMEOUT_MS, SESSIONS, await_result_receiver_timeout, emit_event,
+    ensure_core_initialized, idle, poll_info_system,
+};
+use crate::timer::sleep_ms;
+
+const DEFAULT_BATTERY_POLL_MS: u32 = 30_000;
+/// Longest wait between polls while reads keep failing.
+const MAX_BATTERY_BACKOFF_MS: u32 = 5 * 60_000;
+/// `charge_status` value the watch reports while on the charger.
+const CHARGE_STATUS_CHARGING: u32 = 1;
+
+thread_local! {
+    static NEXT_WATCH_ID: Cell<u32> = const { Cell::new(0) };
+    static BATTERY_WATCHES: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BatteryState {
+    pub level: u8,
+    pub charging: Option<bool>,
+}
+
+/// Reads the battery block out of a device status response. Firmware that
+/// does not report a charging state yields `charging: None`.
+pub(super) fn battery_from_status(status: &DeviceStatus) -> Option<BatteryState> {
+    let battery = status.battery.as_ref()?;
+    Some(BatteryState {
+        level: battery.capacity.min(100) as u8,
+        charging: battery
+            .charge_status
+            .map(|status| status == CHARGE_STATUS_CHARGING),
+    })
+}
+
+fn watch_active(addr: &str, id: u32) -> bool {
+    BATTERY_WATCHES.with(|cell| cell.borrow().get(addr) == Some(&id))
+}
+
+/// Polled through `idle::probe`, so a battery widget left open doesn't keep
+/// an idle session alive.
+async fn read_battery(addr: &str) -> Result<Option<BatteryState>, String> {
+    idle::probe(addr, async {
+        let rx = poll_info_system(addr, |sys| Ok(sys.request_device_status())).await?;
+        let status = await_result_receiver_timeout(
+            rx,
+            "Device status response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await
+        .map_err(|err| err.message)?;
+        Ok(battery_from_status(&status))
+    })
+    .await
+}
+
+/// Emits `battery-changed` whenever the reported level or charging state
+/// changes. corelib does not forward battery pushes, so this polls the device
+/// status every `interval_ms` (30s by default) until unsubscribed or the
+/// device goes away. A failed read emits `battery-error` with `{ message }`
+/// and doubles the wait before the next poll, up to 5 minutes, until a read
+/// succeeds again.
+#[wasm_bindgen]
+pub fn device_subscribe_battery(addr: String, interval_ms: Option<u32>) {
+    ensure_core_initialized();
+    if BATTERY_WATCHES.with(|cell| cell.borrow().contains_key(&addr)) {
+        return;
+    }
+
+    let id = NEXT_WATCH_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    BATTERY_WATCHES.with(|cell| {
+        cell.borrow_mut().insert(addr.clone(), id);
+    });
+
+    let interval = interval_ms.unwrap_or(DEFAULT_BATTERY_POLL_MS);
+    spawn_local(async move {
+        let mut last: Option<BatteryState> = None;
+        let mut delay = interval;
+        while watch_active(&addr, id) && SESSIONS.with(|cell| cell.borrow().contains_key(&addr)) {
+            match read_battery(&addr).await {
+                Ok(state) => {
+                    delay = interval;
+                    if let Some(state) = state
+                        && last != Some(state)
+                    {
+                        last = Some(state);
+                        if let Ok(payload) = to_js_value(&state) {
+                            emit_event("battery-changed", &addr, &payload);
+                        }
+                    }
+                }
+                Err(err) => {
+                    web_sys::console::warn_1(&JsValue::from_str(&format!(
+                        "[wasm] Battery poll for {} failed: {}",
+                        addr, err
+                    )));
+                    let payload = js_sys::Object::new();
+                    let _ = js_sys::Reflect::set(
+                        &payload,
+                        &JsValue::from_str("message"),
+                        &JsValue::from_str(&err),
+                    );
+                    emit_event("battery-error", &addr, &payload);
+                    delay = delay
+                        .saturating_mul(2)
+                        .min(MAX_BATTERY_BACKOFF_MS.max(interval));
+                }
+            }
+            sleep_ms(delay).await;
+        }
+        if watch_active(&addr, id) {
+            BATTERY_WATCHES.with(|cell| {
+                cell.borrow_mut().remove(&addr);
+            });
+        }
+    });
+}
+
+#[wasm_bindgen]
+pub fn device_unsubscribe_battery(addr: String) -> bool {
+    BATTERY_WATCHES.with(|cell| cell.borrow_mut().remove(&addr).is_some())
+}
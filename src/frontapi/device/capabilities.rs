@@ -0,0 +1,42 @@
+use corelib::device::xiaomi::components::install::InstallSystem;
+use corelib::device::xiaomi::components::thirdparty_app::ThirdpartyAppSystem;
+use corelib::device::xiaomi::components::watchface::WatchfaceSystem;
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::ensure_core_initialized;
+use crate::error::{DeviceError, ErrorCode};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Capabilities {
+    pub quick_apps: bool,
+    pub watchfaces: bool,
+    pub watchface_reorder: bool,
+    pub firmware_ota: bool,
+    pub file_read: bool,
+}
+
+/// Reports what the connected device can be asked to do, based on which
+/// systems corelib attached to its entity. `firmware_ota` follows the install
+/// system, which `device_install_firmware` sends images through. Reordering
+/// faces and reading files have no corelib support and are always `false`.
+#[wasm_bindgen]
+pub async fn device_get_capabilities(addr: String) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let owned = addr.clone();
+    let capabilities = corelib::ecs::with_rt_mut(move |rt| {
+        rt.with_device_mut(&owned, |world, entity| Capabilities {
+            quick_apps: world.get::<ThirdpartyAppSystem>(entity).is_some(),
+            watchfaces: world.get::<WatchfaceSystem>(entity).is_some(),
+            watchface_reorder: false,
+            firmware_ota: world.get::<InstallSystem>(entity).is_some(),
+            file_read: false,
+        })
+    })
+    .await
+    .ok_or_else(|| DeviceError::new(ErrorCode::DeviceNotFound, "Device not found"))?;
+
+    to_js_value(&capabilities).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
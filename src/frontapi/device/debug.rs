@@ -0,0 +1,131 @@
+use corelib::device::xiaomi::components::info::InfoSystem;
+use corelib::device::xiaomi::components::install::InstallSystem;
+use corelib::device::xiaomi::components::resource::{ResourceComponent, ResourceSystem};
+use corelib::device::xiaomi::components::thirdparty_app::ThirdpartyAppSystem;
+use corelib::device::xiaomi::components::watchface::WatchfaceSystem;
+use corelib::device::{Device, DeviceKind};
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::{cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::{LABELS, SESSIONS, Session, ensure_core_initialized};
+use crate::spp::xiaomi::ConnectionState;
+
+/// Link details corelib doesn't keep, recorded as sessions go through their
+/// lifecycle.
+#[derive(Debug, Clone, Default)]
+struct LinkInfo {
+    connect_type: Option<String>,
+    sar_version: Option<u32>,
+    state: Option<ConnectionState>,
+    last_seen_ms: Option<f64>,
+}
+
+thread_local! {
+    static LINKS: RefCell<HashMap<String, LinkInfo>> = RefCell::new(HashMap::new());
+}
+
+fn update_link(addr: &str, f: impl FnOnce(&mut LinkInfo)) {
+    LINKS.with(|cell| f(cell.borrow_mut().entry(addr.to_string()).or_default()));
+}
+
+pub(super) fn record_session(addr: &str, connect_type: &str, sar_version: u32) {
+    update_link(addr, |link| {
+        link.connect_type = Some(connect_type.to_string());
+        link.sar_version = Some(sar_version);
+    });
+}
+
+pub(super) fn record_state(addr: &str, state: ConnectionState) {
+    update_link(addr, |link| link.state = Some(state));
+}
+
+pub(super) fn record_inbound(addr: &str) {
+    update_link(addr, |link| link.last_seen_ms = Some(js_sys::Date::now()));
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceSnapshot {
+    name: String,
+    label: Option<String>,
+    addr: String,
+    kind: DeviceKind,
+    transport: Option<&'static str>,
+    connect_type: Option<String>,
+    sar_version: Option<u32>,
+    state: Option<ConnectionState>,
+    last_seen_ms: Option<f64>,
+    components: Vec<&'static str>,
+}
+
+/// Walks every device entity and reports what corelib and this module know
+/// about it, for diagnostics screens.
+#[wasm_bindgen]
+pub async fn device_debug_dump() -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let entities = corelib::ecs::with_rt_mut(|rt| {
+        let ids = rt.device_ids().cloned().collect::<Vec<_>>();
+        ids.into_iter()
+            .filter_map(|device_id| {
+                let (name, addr, kind) = rt
+                    .component_ref::<Device>(&device_id)
+                    .map(|dev| (dev.name().to_string(), dev.addr().to_string(), dev.kind()))?;
+                let components = rt
+                    .with_device_mut(&addr, |world, entity| {
+                        let present = [
+                            ("info", world.get::<InfoSystem>(entity).is_some()),
+                            ("install", world.get::<InstallSystem>(entity).is_some()),
+                            ("resource", world.get::<ResourceSystem>(entity).is_some()),
+                            (
+                                "resource_cache",
+                                world.get::<ResourceComponent>(entity).is_some(),
+                            ),
+                            (
+                                "thirdparty_app",
+                                world.get::<ThirdpartyAppSystem>(entity).is_some(),
+                            ),
+                            ("watchface", world.get::<WatchfaceSystem>(entity).is_some()),
+                        ];
+                        present
+                            .into_iter()
+                            .filter_map(|(name, loaded)| loaded.then_some(name))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                Some((name, addr, kind, components))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    let snapshots = entities
+        .into_iter()
+        .map(|(name, addr, kind, components)| {
+            let link = LINKS.with(|cell| cell.borrow().get(&addr).cloned().unwrap_or_default());
+            let transport = SESSIONS.with(|cell| {
+                cell.borrow().get(&addr).map(|session| match session {
+                    Session::Spp(_) => "spp",
+                    Session::Ble(_) => "ble",
+                    #[cfg(feature = "mock")]
+                    Session::Mock(_) => "mock",
+                })
+            });
+            DeviceSnapshot {
+                name,
+                label: LABELS.with(|cell| cell.borrow().get(&addr).cloned()),
+                transport,
+                connect_type: link.connect_type,
+                sar_version: link.sar_version,
+                state: link.state,
+                last_seen_ms: link.last_seen_ms,
+                components,
+                addr,
+                kind,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    to_js_value(&snapshots).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
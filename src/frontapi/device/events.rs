@@ -0,0 +1,223 @@
+use js_sys::{Function, Object, Reflect};
+use std::{cell::Cell, cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use crate::error::{DeviceError, ErrorCode};
+use crate::spp::xiaomi::current_dispatch_addr;
+use crate::timer::sleep_ms;
+
+thread_local! {
+    static EVENT_SINK: RefCell<Option<Function>> = RefCell::new(None);
+    static LISTENERS: RefCell<HashMap<String, Vec<(u32, Function)>>> = RefCell::new(HashMap::new());
+    static NEXT_LISTENER_ID: Cell<u32> = const { Cell::new(1) };
+    static SINK_FAILURES: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Consecutive throws after which the catch-all sink is dropped.
+const MAX_SINK_FAILURES: u32 = 5;
+
+/// Emits `event` to the catch-all sink and every listener registered for it,
+/// tagging object payloads with the originating `addr` so listeners can tell
+/// concurrent sessions apart.
+pub(crate) fn emit_event(event: &str, addr: &str, payload: &JsValue) {
+    let addr_key = JsValue::from_str("addr");
+    if payload.is_object() && !Reflect::has(payload, &addr_key).unwrap_or(false) {
+        let _ = Reflect::set(payload, &addr_key, &JsValue::from_str(addr));
+    }
+
+    // Callbacks are collected first so a listener may (un)subscribe while handling.
+    let sink = EVENT_SINK.with(|cell| cell.borrow().clone());
+    let listeners = LISTENERS.with(|cell| {
+        cell.borrow()
+            .get(event)
+            .map(|entries| entries.iter().map(|(_, cb)| cb.clone()).collect::<Vec<_>>())
+            .unwrap_or_default()
+    });
+
+    let event_name = JsValue::from_str(event);
+    if let Some(sink) = sink {
+        match sink.call2(&JsValue::NULL, &event_name, payload) {
+            Ok(_) => SINK_FAILURES.with(|failures| failures.set(0)),
+            Err(err) => on_sink_failure(&sink, err),
+        }
+    }
+    for listener in listeners {
+        if let Err(err) = listener.call1(&JsValue::NULL, payload) {
+            web_sys::console::error_2(&JsValue::from_str("event listener failed"), &err);
+        }
+    }
+}
+
+/// Only the first throw of a run is logged so a broken sink can't flood the
+/// console during progress bursts; once the run reaches `MAX_SINK_FAILURES`
+/// the sink is unregistered and `event-sink-removed` goes to the listeners.
+fn on_sink_failure(sink: &Function, err: JsValue) {
+    let failures = SINK_FAILURES.with(|failures| {
+        let count = failures.get().saturating_add(1);
+        failures.set(count);
+        count
+    });
+    if failures == 1 {
+        web_sys::console::error_2(&JsValue::from_str("emit_event failed"), &err);
+    }
+    if failures < MAX_SINK_FAILURES {
+        return;
+    }
+
+    // The sink may have been replaced from inside the throwing call.
+    let removed = EVENT_SINK.with(|cell| {
+        let mut current = cell.borrow_mut();
+        if current.as_ref().is_some_and(|cb| cb == sink) {
+            current.take();
+            true
+        } else {
+            false
+        }
+    });
+    SINK_FAILURES.with(|failures| failures.set(0));
+    if !removed {
+        return;
+    }
+
+    web_sys::console::warn_1(&JsValue::from_str(&format!(
+        "[wasm] Event sink threw {} times in a row and was unregistered",
+        failures
+    )));
+    let payload = Object::new();
+    let _ = Reflect::set(&payload, &JsValue::from_str("addr"), &JsValue::NULL);
+    let _ = Reflect::set(
+        &payload,
+        &JsValue::from_str("failures"),
+        &JsValue::from(failures),
+    );
+    let _ = Reflect::set(&payload, &JsValue::from_str("error"), &err);
+    emit_event("event-sink-removed", "", &payload);
+}
+
+/// Brackets a list request with `list-loading` events, `state` going from
+/// `started` to `finished` or `error`, so hosts can show a spinner.
+pub(crate) async fn with_list_loading<T, E>(
+    addr: &str,
+    kind: &str,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let emit = |state: &str| {
+        let payload = Object::new();
+        let _ = Reflect::set(
+            &payload,
+            &JsValue::from_str("kind"),
+            &JsValue::from_str(kind),
+        );
+        let _ = Reflect::set(
+            &payload,
+            &JsValue::from_str("state"),
+            &JsValue::from_str(state),
+        );
+        emit_event("list-loading", addr, &payload);
+    };
+    emit("started");
+    let result = request.await;
+    emit(if result.is_ok() { "finished" } else { "error" });
+    result
+}
+
+#[wasm_bindgen]
+pub fn register_event_sink(callback: Function) {
+    EVENT_SINK.with(|cell| {
+        *cell.borrow_mut() = Some(callback);
+    });
+    SINK_FAILURES.with(|failures| failures.set(0));
+}
+
+#[wasm_bindgen]
+pub fn device_add_event_listener(event: String, callback: Function) -> u32 {
+    let id = NEXT_LISTENER_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    LISTENERS.with(|cell| {
+        cell.borrow_mut()
+            .entry(event)
+            .or_default()
+            .push((id, callback));
+    });
+    id
+}
+
+#[wasm_bindgen]
+pub fn device_remove_event_listener(id: u32) -> bool {
+    LISTENERS.with(|cell| {
+        let mut listeners = cell.borrow_mut();
+        let mut removed = false;
+        listeners.retain(|_, entries| {
+            let before = entries.len();
+            entries.retain(|(entry_id, _)| *entry_id != id);
+            removed |= entries.len() != before;
+            !entries.is_empty()
+        });
+        removed
+    })
+}
+
+/// Resolves with the payload of the next `event`, or rejects with `Timeout`
+/// once `timeout_ms` pass without one. The listener is removed either way.
+#[wasm_bindgen]
+pub async fn device_once(event: String, timeout_ms: Option<u32>) -> Result<JsValue, JsValue> {
+    let mut resolve_fn = None;
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        resolve_fn = Some(resolve);
+    });
+    let Some(resolve) = resolve_fn else {
+        return Err(DeviceError::new(ErrorCode::Internal, "Promise executor did not run").into());
+    };
+    let id = device_add_event_listener(event.clone(), resolve);
+    let next = wasm_bindgen_futures::JsFuture::from(promise);
+
+    let result = match timeout_ms {
+        Some(timeout_ms) => tokio::select! {
+            biased;
+            payload = next => payload,
+            _ = sleep_ms(timeout_ms) => Err(DeviceError::new(
+                ErrorCode::Timeout,
+                format!("No {event} event within {timeout_ms}ms"),
+            )
+            .into()),
+        },
+        None => next.await,
+    };
+    device_remove_event_listener(id);
+    result
+}
+
+/// Reports a panic as `internal-error` with `{ addr, message, backtrace }`,
+/// `addr` being set when it happened while dispatching a device's frame.
+/// wasm32 aborts on panic, so this is the last event the module emits and
+/// the host has to reload it to recover.
+pub(super) fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        console_error_panic_hook::hook(info);
+
+        let addr = current_dispatch_addr();
+        let payload = Object::new();
+        let _ = Reflect::set(
+            &payload,
+            &JsValue::from_str("addr"),
+            &addr.as_deref().map_or(JsValue::NULL, JsValue::from_str),
+        );
+        let _ = Reflect::set(
+            &payload,
+            &JsValue::from_str("message"),
+            &JsValue::from_str(&info.to_string()),
+        );
+        let stack = Reflect::get(&js_sys::Error::new(""), &JsValue::from_str("stack"))
+            .unwrap_or(JsValue::NULL);
+        let _ = Reflect::set(&payload, &JsValue::from_str("backtrace"), &stack);
+        emit_event(
+            "internal-error",
+            addr.as_deref().unwrap_or_default(),
+            &payload,
+        );
+    }));
+}
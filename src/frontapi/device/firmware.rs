@@ -0,0 +1,229 @@
+use corelib::device::xiaomi::components::info::DeviceInfo;
+use corelib::device::xiaomi::packet::mass::MassDataType;
+use js_sys::{Function, Uint8Array};
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::cmp::Ordering;
+use std::{cell::RefCell, collections::HashSet};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::install::{InstallOptions, install_package};
+use super::{
+    DEFAULT_REQUEST_TIMEOUT_MS, await_result_receiver_timeout, ensure_core_initialized,
+    with_info_system,
+};
+use crate::error::{DeviceError, ErrorCode};
+
+thread_local! {
+    static EXPECTED_REBOOTS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// True while `addr` is expected to drop the link to reboot into new firmware.
+pub(super) fn reboot_expected(addr: &str) -> bool {
+    EXPECTED_REBOOTS.with(|cell| cell.borrow().contains(addr))
+}
+
+pub(super) fn take_expected_reboot(addr: &str) -> bool {
+    EXPECTED_REBOOTS.with(|cell| cell.borrow_mut().remove(addr))
+}
+
+/// The firmware version string of a device info response, `None` when the
+/// device left it empty.
+pub(super) fn firmware_from_info(info: &DeviceInfo) -> Option<String> {
+    let version = info.firmware.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct FirmwareVersion {
+    parts: Vec<u64>,
+    pre_release: Option<String>,
+}
+
+impl FirmwareVersion {
+    /// Parses `1.2.3`, `v1.2.3-beta.1`, `2.1.79+build5` and vendor variants
+    /// like `3.0.26_CN`. Build metadata and region suffixes are ignored.
+    fn parse(version: &str) -> Option<Self> {
+        let version = version.trim().trim_start_matches(['v', 'V']);
+        let version = version.split(['+', '_']).next().unwrap_or_default();
+        let (core, pre_release) = match version.split_once('-') {
+            Some((core, pre)) => (core, Some(pre.to_string())),
+            None => (version, None),
+        };
+        let parts = core
+            .split('.')
+            .map(|part| {
+                let digits = part
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>();
+                digits.parse::<u64>().ok()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if parts.is_empty() {
+            return None;
+        }
+        Some(Self { parts, pre_release })
+    }
+}
+
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.parts.len().max(other.parts.len());
+        for idx in 0..len {
+            let lhs = self.parts.get(idx).copied().unwrap_or(0);
+            let rhs = other.parts.get(idx).copied().unwrap_or(0);
+            match lhs.cmp(&rhs) {
+                Ordering::Equal => {}
+                ord => return ord,
+            }
+        }
+        // A pre-release sorts before the release it leads up to.
+        match (&self.pre_release, &other.pre_release) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(lhs), Some(rhs)) => compare_pre_release(lhs, rhs),
+        }
+    }
+}
+
+/// Semver precedence of two pre-release tags: dot-separated identifiers are
+/// compared in turn, numerically when both are numbers, and numeric ones sort
+/// before alphanumeric ones. A tag that runs out first sorts lower.
+fn compare_pre_release(lhs: &str, rhs: &str) -> Ordering {
+    let mut lhs_ids = lhs.split('.');
+    let mut rhs_ids = rhs.split('.');
+    loop {
+        let ord = match (lhs_ids.next(), rhs_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(lhs), Some(rhs)) => match (lhs.parse::<u64>(), rhs.parse::<u64>()) {
+                (Ok(lhs), Ok(rhs)) => lhs.cmp(&rhs),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => lhs.cmp(rhs),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn parse_version(version: &str) -> Result<FirmwareVersion, DeviceError> {
+    FirmwareVersion::parse(version).ok_or_else(|| {
+        DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unrecognised firmware version: {version}"),
+        )
+    })
+}
+
+#[wasm_bindgen]
+pub async fn device_firmware_at_least(addr: String, min_version: String) -> Result<bool, JsValue> {
+    ensure_core_initialized();
+    let minimum = parse_version(&min_version)?;
+
+    let rx = with_info_system(&addr, |sys| Ok(sys.request_device_info()))
+        .await
+        .map_err(DeviceError::from_core)?;
+    let info = await_result_receiver_timeout(
+        rx,
+        "Device info response not received",
+        DEFAULT_REQUEST_TIMEOUT_MS,
+    )
+    .await?;
+    let reported = firmware_from_info(&info).ok_or_else(|| {
+        DeviceError::new(
+            ErrorCode::Unsupported,
+            "Firmware version not reported by device",
+        )
+    })?;
+    Ok(parse_version(&reported)? >= minimum)
+}
+
+/// Pushes a firmware image and resolves with an `InstallResult`. Progress goes
+/// through the `transferring`, `verifying` and `rebooting` phases, and the
+/// disconnect caused by the reboot is reported with
+/// `expected_after_update: true`.
+#[wasm_bindgen]
+pub async fn device_install_firmware(
+    addr: String,
+    data: Uint8Array,
+    progress_cb: Option<Function>,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let mut options = InstallOptions::from_js(options)?;
+    options.firmware = true;
+
+    // The device may already drop the link while it applies the image, before
+    // the install future has resolved.
+    EXPECTED_REBOOTS.with(|cell| {
+        cell.borrow_mut().insert(addr.clone());
+    });
+    let result = install_package(
+        addr.clone(),
+        MassDataType::Firmware,
+        data.to_vec(),
+        None,
+        progress_cb,
+        None,
+        options,
+    )
+    .await;
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            take_expected_reboot(&addr);
+            return Err(err);
+        }
+    };
+    to_js_value(&result).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn version(text: &str) -> FirmwareVersion {
+        FirmwareVersion::parse(text).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn parses_vendor_variants() {
+        assert_eq!(version("v1.2.3").parts, [1, 2, 3]);
+        assert_eq!(version("2.1.79+build5").parts, [2, 1, 79]);
+        assert_eq!(version("3.0.26_CN").parts, [3, 0, 26]);
+        assert_eq!(
+            version("1.2.3-beta.1").pre_release.as_deref(),
+            Some("beta.1")
+        );
+        assert!(FirmwareVersion::parse("beta").is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn orders_release_parts_numerically() {
+        assert!(version("1.10.0") > version("1.9.9"));
+        assert_eq!(version("1.2"), version("1.2"));
+        assert_eq!(version("1.2").cmp(&version("1.2.0")), Ordering::Equal);
+    }
+
+    #[wasm_bindgen_test]
+    fn orders_pre_releases_by_semver_rules() {
+        assert!(version("1.0.0-beta") < version("1.0.0"));
+        assert!(version("1.0.0-beta.2") < version("1.0.0-beta.10"));
+        assert!(version("1.0.0-alpha") < version("1.0.0-alpha.1"));
+        assert!(version("1.0.0-1") < version("1.0.0-alpha"));
+        assert!(version("1.0.0-alpha") < version("1.0.0-beta"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::{cell::Cell, cell::RefCell, collections::HashMap};
+use wasm_bindgen_futures::spawn_local;
+
+use super::{SESSIONS, handle_remote_disconnect};
+use crate::spp::xiaomi::DisconnectReason;
+use crate::timer::sleep_ms;
+
+thread_local! {
+    static NEXT_IDLE_ID: Cell<u32> = const { Cell::new(0) };
+    /// `(watch id, timeout, last activity)` per session with an idle timeout.
+    static IDLE_WATCHES: RefCell<HashMap<String, (u32, u32, f64)>> = RefCell::new(HashMap::new());
+    /// Internal polls per session still waiting for their response frame.
+    static PROBES: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+fn watch_state(addr: &str, id: u32) -> Option<(u32, f64)> {
+    if !SESSIONS.with(|cell| cell.borrow().contains_key(addr)) {
+        return None;
+    }
+    IDLE_WATCHES.with(|cell| {
+        cell.borrow()
+            .get(addr)
+            .filter(|(watch_id, _, _)| *watch_id == id)
+            .map(|(_, timeout, last)| (*timeout, *last))
+    })
+}
+
+/// Marks `addr` as in use. Called for every command and inbound frame; the
+/// requests of internal polls go around it, see `probe`.
+pub(super) fn touch(addr: &str) {
+    IDLE_WATCHES.with(|cell| {
+        if let Some((_, _, last)) = cell.borrow_mut().get_mut(addr) {
+            *last = js_sys::Date::now();
+        }
+    });
+}
+
+/// `touch` for an inbound frame. While an internal poll waits, the next frame
+/// is taken as its response and doesn't count as activity.
+pub(super) fn touch_inbound(addr: &str) {
+    if !take_probe(addr) {
+        touch(addr);
+    }
+}
+
+fn take_probe(addr: &str) -> bool {
+    PROBES.with(|cell| {
+        let mut probes = cell.borrow_mut();
+        let Some(pending) = probes.get_mut(addr) else {
+            return false;
+        };
+        *pending -= 1;
+        if *pending == 0 {
+            probes.remove(addr);
+        }
+        true
+    })
+}
+
+/// Runs an internal poll such as a keepalive ping or a battery read, so it
+/// doesn't hold an idle link open. `request` has to reach the device without
+/// `touch`; its response frame is skipped by `touch_inbound`, and a poll that
+/// fails stops waiting for one.
+pub(super) async fn probe<T, E>(
+    addr: &str,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    PROBES.with(|cell| *cell.borrow_mut().entry(addr.to_string()).or_insert(0) += 1);
+    let result = request.await;
+    if result.is_err() {
+        take_probe(addr);
+    }
+    result
+}
+
+/// Disconnects `addr` with reason `idle` once `timeout_ms` pass without a
+/// command or inbound frame.
+pub(super) fn start_idle_watch(addr: String, timeout_ms: u32) {
+    let id = NEXT_IDLE_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    IDLE_WATCHES.with(|cell| {
+        cell.borrow_mut()
+            .insert(addr.clone(), (id, timeout_ms, js_sys::Date::now()));
+    });
+
+    spawn_local(async move {
+        let mut wait_ms = timeout_ms;
+        loop {
+            sleep_ms(wait_ms).await;
+            let Some((timeout, last)) = watch_state(&addr, id) else {
+                break;
+            };
+            let idle_for = js_sys::Date::now() - last;
+            if idle_for >= f64::from(timeout) {
+                log::info!("[wasm] Closing {} after {}ms idle", addr, idle_for as u64);
+                handle_remote_disconnect(addr.clone(), DisconnectReason::Idle).await;
+                break;
+            }
+            wait_ms = (f64::from(timeout) - idle_for).ceil() as u32;
+        }
+    });
+}
+
+pub(super) fn stop_idle_watch(addr: &str) {
+    IDLE_WATCHES.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+    PROBES.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+}
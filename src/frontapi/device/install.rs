@@ -0,0 +1,872 @@
+use async_channel::unbounded;
+use corelib::device::xiaomi::components::mass::SendMassCallbackData;
+use corelib::device::xiaomi::packet::mass::MassDataType;
+use js_sys::{Function, Object, Reflect, Uint8Array};
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value as from_js_value, to_value as to_js_value};
+use std::sync::Arc;
+use std::{cell::Cell, cell::RefCell, collections::HashMap, collections::VecDeque, rc::Rc};
+use tokio::sync::{Mutex, oneshot};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use super::package::{PackageInfo, parse_package};
+use super::paging;
+use super::storage::free_storage;
+use super::{command_lock, ensure_core_initialized, session_is_ble, with_install_system};
+use crate::error::{DeviceError, ErrorCode};
+
+thread_local! {
+    static NEXT_INSTALL_ID: Cell<u32> = const { Cell::new(0) };
+    static CANCEL_HANDLES: RefCell<HashMap<String, (u32, oneshot::Sender<()>)>> =
+        RefCell::new(HashMap::new());
+    static INSTALL_LOCKS: RefCell<HashMap<String, Rc<Mutex<()>>>> = RefCell::new(HashMap::new());
+    static QUEUED_INSTALLS: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+    static RECENT_RATES: RefCell<HashMap<String, VecDeque<f64>>> = RefCell::new(HashMap::new());
+    /// When an install was last cancelled per device, see `check_settled`.
+    static CANCELLED_AT: RefCell<HashMap<String, f64>> = RefCell::new(HashMap::new());
+    /// Latest progress of the install running per device, tagged with its install id.
+    static ACTIVE_INSTALLS: RefCell<HashMap<String, (u32, ActiveInstall)>> =
+        RefCell::new(HashMap::new());
+}
+
+fn install_lock(addr: &str) -> Rc<Mutex<()>> {
+    INSTALL_LOCKS.with(|cell| {
+        cell.borrow_mut()
+            .entry(addr.to_string())
+            .or_insert_with(|| Rc::new(Mutex::new(())))
+            .clone()
+    })
+}
+
+/// True while an install holds the transfer slot of `addr`.
+pub(super) fn install_running(addr: &str) -> bool {
+    INSTALL_LOCKS.with(|cell| {
+        cell.borrow()
+            .get(addr)
+            .is_some_and(|lock| lock.try_lock().is_err())
+    })
+}
+
+/// How long after a cancel the device is assumed to still be receiving. It
+/// is never told to abort, so it only gives up once its own session times out.
+const CANCEL_SETTLE_MS: f64 = 30_000.0;
+
+/// Rejects with `DeviceBusy` while a cancelled transfer may still be running
+/// on the device.
+fn check_settled(addr: &str) -> Result<(), DeviceError> {
+    let busy = CANCELLED_AT.with(|cell| {
+        let mut cancelled = cell.borrow_mut();
+        let Some(at) = cancelled.get(addr).copied() else {
+            return false;
+        };
+        if js_sys::Date::now() - at < CANCEL_SETTLE_MS {
+            return true;
+        }
+        cancelled.remove(addr);
+        false
+    });
+    if busy {
+        return Err(DeviceError::new(
+            ErrorCode::DeviceBusy,
+            "The device may still be receiving a cancelled install",
+        ));
+    }
+    Ok(())
+}
+
+/// Drops the cancel record of `addr` once its session is gone, since a new
+/// session starts without a transfer in flight.
+pub(super) fn forget_cancelled(addr: &str) {
+    CANCELLED_AT.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+}
+
+fn adjust_queued(addr: &str, queued: bool) {
+    QUEUED_INSTALLS.with(|cell| {
+        let mut counts = cell.borrow_mut();
+        let count = counts.entry(addr.to_string()).or_insert(0);
+        if queued {
+            *count += 1;
+        } else {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(addr);
+            }
+        }
+    });
+}
+
+fn register_cancel_handle(addr: &str) -> (u32, oneshot::Receiver<()>) {
+    let id = NEXT_INSTALL_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    let (tx, rx) = oneshot::channel();
+    CANCEL_HANDLES.with(|cell| {
+        cell.borrow_mut().insert(addr.to_string(), (id, tx));
+    });
+    (id, rx)
+}
+
+fn release_cancel_handle(addr: &str, id: u32) {
+    CANCEL_HANDLES.with(|cell| {
+        let mut handles = cell.borrow_mut();
+        if handles.get(addr).is_some_and(|(current, _)| *current == id) {
+            handles.remove(addr);
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallPhase {
+    Transferring,
+    Applying,
+    Verifying,
+    Completed,
+    Rebooting,
+    Failed,
+}
+
+enum ProgressUpdate {
+    Mass(SendMassCallbackData),
+    Finished(Result<(), String>),
+}
+
+impl ProgressUpdate {
+    /// Firmware images are verified rather than applied once transferred, and
+    /// a successful update ends with the device rebooting.
+    fn phase(&self, firmware: bool) -> InstallPhase {
+        match self {
+            // Once the last part is out the device is unpacking and applying the package.
+            Self::Mass(data) if data.current_part_num >= data.total_parts => {
+                if firmware {
+                    InstallPhase::Verifying
+                } else {
+                    InstallPhase::Applying
+                }
+            }
+            Self::Mass(_) => InstallPhase::Transferring,
+            Self::Finished(Ok(())) if firmware => InstallPhase::Rebooting,
+            Self::Finished(Ok(())) => InstallPhase::Completed,
+            Self::Finished(Err(_)) => InstallPhase::Failed,
+        }
+    }
+
+    fn to_js(&self, phase: InstallPhase) -> Result<JsValue, String> {
+        let payload: JsValue = match self {
+            Self::Mass(data) => to_js_value(data).map_err(|err| err.to_string())?,
+            Self::Finished(_) => Object::new().into(),
+        };
+        let phase = to_js_value(&phase).map_err(|err| err.to_string())?;
+        Reflect::set(&payload, &JsValue::from_str("phase"), &phase)
+            .map_err(|err| format!("{:?}", err))?;
+        if let Self::Finished(Err(message)) = self {
+            Reflect::set(
+                &payload,
+                &JsValue::from_str("error"),
+                &JsValue::from_str(message),
+            )
+            .map_err(|err| format!("{:?}", err))?;
+        }
+        Ok(payload)
+    }
+
+    fn is_terminal(&self) -> bool {
+        matches!(self, Self::Finished(_))
+    }
+}
+
+/// Snapshot returned by `device_get_active_install`.
+#[derive(Debug, Clone, Copy, Serialize)]
+struct ActiveInstall {
+    phase: InstallPhase,
+    percent: f64,
+    bytes_sent: usize,
+    total: usize,
+}
+
+impl ActiveInstall {
+    fn new(total: usize) -> Self {
+        Self {
+            phase: InstallPhase::Transferring,
+            percent: 0.0,
+            bytes_sent: 0,
+            total,
+        }
+    }
+}
+
+fn set_active_install(addr: &str, id: u32, install: ActiveInstall) {
+    ACTIVE_INSTALLS.with(|cell| {
+        cell.borrow_mut().insert(addr.to_string(), (id, install));
+    });
+}
+
+fn update_active_install(addr: &str, id: u32, phase: InstallPhase, data: &SendMassCallbackData) {
+    ACTIVE_INSTALLS.with(|cell| {
+        let mut installs = cell.borrow_mut();
+        let Some((_, install)) = installs.get_mut(addr).filter(|(current, _)| *current == id)
+        else {
+            return;
+        };
+        let fraction = sent_fraction(data);
+        install.phase = phase;
+        install.percent = (fraction * 1000.0).round() / 10.0;
+        install.bytes_sent = (install.total as f64 * fraction) as usize;
+    });
+}
+
+fn clear_active_install(addr: &str, id: u32) {
+    ACTIVE_INSTALLS.with(|cell| {
+        let mut installs = cell.borrow_mut();
+        if installs
+            .get(addr)
+            .is_some_and(|(current, _)| *current == id)
+        {
+            installs.remove(addr);
+        }
+    });
+}
+
+/// Share of the transfer's parts sent so far, from 0 to 1.
+fn sent_fraction(data: &SendMassCallbackData) -> f64 {
+    if data.total_parts == 0 {
+        0.0
+    } else {
+        f64::from(data.current_part_num.min(data.total_parts)) / f64::from(data.total_parts)
+    }
+}
+
+/// Drops intermediate transfer updates that arrive within `interval_ms` of
+/// the last forwarded one or move the percentage by less than `min_percent`;
+/// with both set an update has to clear both. Phase changes and the final
+/// update always pass.
+struct ProgressThrottle {
+    interval_ms: Option<f64>,
+    min_percent: Option<f64>,
+    last_sent: Option<f64>,
+    last_percent: Option<f64>,
+    last_phase: Option<InstallPhase>,
+}
+
+impl ProgressThrottle {
+    fn new(interval_ms: Option<u32>, min_percent: Option<f64>) -> Self {
+        Self {
+            interval_ms: interval_ms.filter(|ms| *ms > 0).map(f64::from),
+            min_percent: min_percent.filter(|percent| *percent > 0.0),
+            last_sent: None,
+            last_percent: None,
+            last_phase: None,
+        }
+    }
+
+    /// `percent` is how far the transfer is and `now` the current time in ms.
+    fn should_forward(
+        &mut self,
+        terminal: bool,
+        phase: InstallPhase,
+        percent: f64,
+        now: f64,
+    ) -> bool {
+        let forward = terminal
+            || self.last_phase != Some(phase)
+            || (self
+                .interval_ms
+                .zip(self.last_sent)
+                .is_none_or(|(interval, last)| now - last >= interval)
+                && self
+                    .min_percent
+                    .zip(self.last_percent)
+                    .is_none_or(|(min, last)| (percent - last).abs() >= min));
+        if forward {
+            self.last_sent = Some(now);
+            self.last_percent = Some(percent);
+            self.last_phase = Some(phase);
+        }
+        forward
+    }
+}
+
+/// Window the transfer rate is averaged over, to smooth out per-part jitter.
+const RATE_WINDOW_MS: f64 = 3_000.0;
+
+/// Rolling estimate of transfer speed. Progress callbacks only report parts,
+/// so bytes sent are approximated from the part count and the package size.
+struct TransferRate {
+    total_bytes: f64,
+    samples: VecDeque<(f64, f64)>,
+}
+
+impl TransferRate {
+    fn new(total_bytes: usize) -> Self {
+        Self {
+            total_bytes: total_bytes as f64,
+            samples: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, data: &SendMassCallbackData) {
+        if data.total_parts == 0 {
+            return;
+        }
+        let now = js_sys::Date::now();
+        let sent = self.total_bytes * f64::from(data.current_part_num.min(data.total_parts))
+            / f64::from(data.total_parts);
+        self.samples.push_back((now, sent));
+        while self.samples.len() > 2
+            && self
+                .samples
+                .front()
+                .is_some_and(|(at, _)| now - at > RATE_WINDOW_MS)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn bytes_per_sec(&self) -> Option<f64> {
+        let (first_at, first_sent) = *self.samples.front()?;
+        let (last_at, last_sent) = *self.samples.back()?;
+        let elapsed = last_at - first_at;
+        (elapsed > 0.0).then(|| (last_sent - first_sent).max(0.0) * 1000.0 / elapsed)
+    }
+
+    fn eta_ms(&self) -> Option<f64> {
+        let rate = self.bytes_per_sec().filter(|rate| *rate > 0.0)?;
+        let (_, sent) = *self.samples.back()?;
+        Some(((self.total_bytes - sent).max(0.0) / rate * 1000.0).round())
+    }
+
+    fn annotate(&self, payload: &JsValue) {
+        let to_js = |value: Option<f64>| value.map_or(JsValue::NULL, JsValue::from_f64);
+        let _ = Reflect::set(
+            payload,
+            &JsValue::from_str("bytes_per_sec"),
+            &to_js(self.bytes_per_sec().map(f64::round)),
+        );
+        let _ = Reflect::set(payload, &JsValue::from_str("eta_ms"), &to_js(self.eta_ms()));
+    }
+}
+
+/// Completed installs per device whose average speed feeds
+/// `device_estimate_install`.
+const RECENT_RATE_SAMPLES: usize = 5;
+/// Smaller transfers are dominated by the handshake and skew the average.
+const MIN_RATE_SAMPLE_BYTES: usize = 16 * 1024;
+/// Fixed cost of the install handshake and the device verifying the package.
+const INSTALL_OVERHEAD_MS: f64 = 2_000.0;
+/// Fallback speeds before any install has completed on a device.
+const DEFAULT_SPP_BYTES_PER_SEC: f64 = 20_000.0;
+const DEFAULT_BLE_BYTES_PER_SEC: f64 = 6_000.0;
+
+fn record_install_rate(addr: &str, bytes: usize, duration_ms: f64) {
+    if bytes < MIN_RATE_SAMPLE_BYTES || duration_ms <= INSTALL_OVERHEAD_MS {
+        return;
+    }
+    let rate = bytes as f64 * 1000.0 / (duration_ms - INSTALL_OVERHEAD_MS);
+    RECENT_RATES.with(|cell| {
+        let mut rates = cell.borrow_mut();
+        let samples = rates.entry(addr.to_string()).or_default();
+        samples.push_back(rate);
+        if samples.len() > RECENT_RATE_SAMPLES {
+            samples.pop_front();
+        }
+    });
+}
+
+fn average_install_rate(addr: &str) -> Option<f64> {
+    RECENT_RATES.with(|cell| {
+        let rates = cell.borrow();
+        let samples = rates.get(addr).filter(|samples| !samples.is_empty())?;
+        Some(samples.iter().sum::<f64>() / samples.len() as f64)
+    })
+}
+
+/// Rough duration in ms of installing `byte_len` bytes on `addr`. corelib
+/// does not expose the negotiated frame size, so this uses the average of
+/// recent installs on the device, or a per-transport default before the first.
+#[wasm_bindgen]
+pub fn device_estimate_install(addr: String, byte_len: u32) -> Result<u32, JsValue> {
+    let is_ble = session_is_ble(&addr)
+        .ok_or_else(|| DeviceError::new(ErrorCode::DeviceNotFound, "Device not found"))?;
+    let rate = average_install_rate(&addr).unwrap_or(if is_ble {
+        DEFAULT_BLE_BYTES_PER_SEC
+    } else {
+        DEFAULT_SPP_BYTES_PER_SEC
+    });
+    let estimate = f64::from(byte_len) * 1000.0 / rate + INSTALL_OVERHEAD_MS;
+    Ok(estimate.round().min(f64::from(u32::MAX)) as u32)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallReport {
+    pub res_type: u8,
+    pub size: usize,
+    pub package: PackageInfo,
+    pub free_storage: Option<u64>,
+    /// `None` when the free space could not be read.
+    pub fits: Option<bool>,
+}
+
+/// Free space for the install checks. A device that doesn't answer is not a
+/// reason to refuse the install, so a failed read counts as unknown.
+async fn read_free_storage(addr: &str) -> Option<u64> {
+    match free_storage(addr).await {
+        Ok(available) => available,
+        Err(err) => {
+            log::warn!("[wasm] Could not read free storage of {}: {}", addr, err);
+            None
+        }
+    }
+}
+
+/// Validates an install without transferring anything. The device exposes no
+/// model data to check packages against, so only the package itself and the
+/// free storage are looked at. `file_name` is only used to tell ABP packages
+/// from plain zips; without it the type is detected by content alone.
+async fn dry_run_install(
+    addr: &str,
+    res_type: u8,
+    file_data: &[u8],
+    file_name: Option<&str>,
+) -> InstallReport {
+    let package = parse_package(file_data, file_name.unwrap_or_default());
+    let free_storage = read_free_storage(addr).await;
+    InstallReport {
+        res_type,
+        size: file_data.len(),
+        package,
+        free_storage,
+        fits: free_storage.map(|free| file_data.len() as u64 <= free),
+    }
+}
+
+/// What `device_install` and `watchface_install` resolve with. InstallSystem
+/// only reports success, so whether an existing item was overwritten is not
+/// known and `assigned_id` is the watchface id the install was sent with.
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallResult {
+    pub installed: bool,
+    pub package_name: Option<String>,
+    pub assigned_id: Option<String>,
+    pub bytes_sent: usize,
+    pub duration_ms: f64,
+    /// Whether the device confirmed a checksum of the received package. The
+    /// install protocol has no way to ask for one, so this is always `false`.
+    pub verified: bool,
+    /// Set by `watchface_install` with `set_active`: whether the face was made
+    /// current. The install itself went through either way.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activated: Option<bool>,
+    /// Why the face could not be made current, when `activated` is `false`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activation_error: Option<DeviceError>,
+}
+
+/// Optional settings accepted by `device_install` and `watchface_install`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct InstallOptions {
+    /// Minimum spacing between forwarded transfer updates.
+    pub progress_throttle_ms: Option<u32>,
+    /// Minimum change in percent between forwarded transfer updates.
+    pub progress_min_percent: Option<f64>,
+    /// Only honoured by `device_install`.
+    pub dry_run: bool,
+    /// Name of the package file, e.g. `app.abp`, for the `dry_run` report.
+    pub file_name: Option<String>,
+    /// Only honoured by `watchface_install`: show the face once installed.
+    pub set_active: bool,
+    #[serde(skip)]
+    pub firmware: bool,
+}
+
+impl InstallOptions {
+    pub(super) fn from_js(options: JsValue) -> Result<Self, DeviceError> {
+        from_js_value::<Option<Self>>(options)
+            .map(Option::unwrap_or_default)
+            .map_err(|err| {
+                DeviceError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Invalid install options: {}", err),
+                )
+            })
+    }
+}
+
+/// Installs a package and resolves with an `InstallResult`, or with `dry_run`
+/// set only validates it and resolves with an `InstallReport`. Cancelling
+/// through `device_cancel_install` only stops sending; the device is not told
+/// to abort.
+#[wasm_bindgen]
+pub async fn device_install(
+    addr: String,
+    res_type: u8,
+    data: Uint8Array,
+    package_name: Option<String>,
+    progress_cb: Option<Function>,
+    watchface_id: Option<String>,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+
+    let options = InstallOptions::from_js(options)?;
+    let data_type = MassDataType::try_from(res_type).map_err(|err| {
+        DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown res_type {}: {}", res_type, err),
+        )
+    })?;
+    if options.dry_run {
+        let report = dry_run_install(
+            &addr,
+            res_type,
+            &data.to_vec(),
+            options.file_name.as_deref(),
+        )
+        .await;
+        return to_js_value(&report).map_err(|err| JsValue::from_str(&format!("{:?}", err)));
+    }
+
+    let result = install_package(
+        addr,
+        data_type,
+        data.to_vec(),
+        package_name,
+        progress_cb,
+        watchface_id,
+        options,
+    )
+    .await?;
+    to_js_value(&result).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// Rejects with `InsufficientStorage`, carrying `required` and `available`,
+/// when the device reports less free space than the package needs. If the
+/// free space can't be read the install goes ahead and the device decides.
+pub(super) async fn check_free_storage(addr: &str, required: usize) -> Result<(), JsValue> {
+    let Some(available) = read_free_storage(addr).await else {
+        return Ok(());
+    };
+    if required as u64 <= available {
+        return Ok(());
+    }
+    let err: JsValue = DeviceError::new(
+        ErrorCode::InsufficientStorage,
+        format!("Package needs {required} bytes but only {available} are free"),
+    )
+    .into();
+    let _ = Reflect::set(
+        &err,
+        &JsValue::from_str("required"),
+        &JsValue::from_f64(required as f64),
+    );
+    let _ = Reflect::set(
+        &err,
+        &JsValue::from_str("available"),
+        &JsValue::from_f64(available as f64),
+    );
+    Err(err)
+}
+
+pub(super) async fn install_package(
+    addr: String,
+    data_type: MassDataType,
+    file_data: Vec<u8>,
+    package_name: Option<String>,
+    progress_cb: Option<Function>,
+    watchface_id: Option<String>,
+    options: InstallOptions,
+) -> Result<InstallResult, JsValue> {
+    // Firmware images are staged outside the user storage the device reports.
+    // Checked before queueing so a package that can't fit fails right away.
+    if !options.firmware {
+        check_free_storage(&addr, file_data.len()).await?;
+    }
+    let slot_addr = addr.clone();
+    with_install_slot(
+        &slot_addr,
+        transfer_package(
+            addr,
+            data_type,
+            file_data,
+            package_name,
+            progress_cb,
+            watchface_id,
+            options,
+        ),
+    )
+    .await
+}
+
+/// Runs `work` once it holds the transfer slot and the command queue of
+/// `addr`, so commands that belong with an install can run before anything
+/// else reaches the device. Rejects with `DeviceBusy` instead for a while
+/// after an install was cancelled, see `check_settled`.
+pub(super) async fn with_install_slot<T>(
+    addr: &str,
+    work: impl Future<Output = Result<T, JsValue>>,
+) -> Result<T, JsValue> {
+    // InstallSystem only tracks one transfer per device, so later installs
+    // wait here until the current one has finished.
+    let lock = install_lock(addr);
+    adjust_queued(addr, true);
+    let _install_guard = lock.lock().await;
+    adjust_queued(addr, false);
+    // Also checked for installs that were queued behind the cancelled one.
+    check_settled(addr)?;
+    // Other mutating commands wait for the transfer to end instead of
+    // interleaving their packets with its parts.
+    let commands = command_lock(addr);
+    let _command_guard = commands.lock().await;
+    work.await
+}
+
+/// Sends a package to the device; the caller holds the install slot.
+pub(super) async fn transfer_package(
+    addr: String,
+    data_type: MassDataType,
+    file_data: Vec<u8>,
+    package_name: Option<String>,
+    progress_cb: Option<Function>,
+    watchface_id: Option<String>,
+    options: InstallOptions,
+) -> Result<InstallResult, JsValue> {
+    let (progress_tx, progress_rx) = unbounded::<ProgressUpdate>();
+    let progress_notifier = {
+        let sender = progress_tx.clone();
+        Arc::new(move |payload: SendMassCallbackData| {
+            let _ = sender.try_send(ProgressUpdate::Mass(payload));
+        }) as Arc<dyn Fn(SendMassCallbackData) + Send + Sync>
+    };
+
+    let total_bytes = file_data.len();
+    let started_at = js_sys::Date::now();
+    let resolved_name = package_name
+        .clone()
+        .or_else(|| parse_package(&file_data, "").package_name);
+    let assigned_id = watchface_id.clone();
+    let package_name_clone = package_name.clone();
+    let install_future = with_install_system(&addr, move |install_sys| {
+        install_sys
+            .send_install_request_with_progress(
+                data_type,
+                file_data,
+                package_name_clone.as_deref(),
+                progress_notifier,
+                watchface_id.as_deref(),
+            )
+            .map_err(|err| format!("{:?}", err))
+    })
+    .await
+    .map_err(DeviceError::from_core)?;
+
+    let (install_id, cancel_rx) = register_cancel_handle(&addr);
+    set_active_install(&addr, install_id, ActiveInstall::new(total_bytes));
+
+    // Always drained, even without a callback, so `device_get_active_install`
+    // keeps reporting where the transfer is.
+    let callback = progress_cb.clone();
+    let progress_addr = addr.clone();
+    spawn_local(async move {
+        let receiver = progress_rx;
+        let mut throttle =
+            ProgressThrottle::new(options.progress_throttle_ms, options.progress_min_percent);
+        let mut percent = 0.0;
+        let mut rate = TransferRate::new(total_bytes);
+        while let Ok(update) = receiver.recv().await {
+            let phase = update.phase(options.firmware);
+            if let ProgressUpdate::Mass(data) = &update {
+                percent = sent_fraction(data) * 100.0;
+                rate.record(data);
+                update_active_install(&progress_addr, install_id, phase, data);
+            }
+            if update.is_terminal() {
+                clear_active_install(&progress_addr, install_id);
+            }
+            if let Some(callback) = callback.as_ref()
+                && throttle.should_forward(
+                    update.is_terminal(),
+                    phase,
+                    percent,
+                    js_sys::Date::now(),
+                )
+            {
+                match update.to_js(phase) {
+                    Ok(js_payload) => {
+                        if phase == InstallPhase::Transferring {
+                            rate.annotate(&js_payload);
+                        }
+                        let _ = callback.call1(&JsValue::NULL, &js_payload);
+                    }
+                    Err(err) => {
+                        web_sys::console::error_1(&JsValue::from_str(&format!(
+                            "[wasm] device_install progress serialization failed: {}",
+                            err
+                        )));
+                    }
+                }
+            }
+            if update.is_terminal() {
+                break;
+            }
+        }
+    });
+
+    // InstallSystem has no abort command, so cancelling stops driving the
+    // transfer on our side and leaves the device to time the session out.
+    let result = tokio::select! {
+        biased;
+        Ok(()) = cancel_rx => {
+            CANCELLED_AT.with(|cell| {
+                cell.borrow_mut().insert(addr.clone(), js_sys::Date::now());
+            });
+            Err(DeviceError::new(ErrorCode::Cancelled, "Install cancelled"))
+        }
+        res = install_future => res.map_err(|err| DeviceError::from_core(format!("{:?}", err))),
+    };
+    release_cancel_handle(&addr, install_id);
+    // The device lists may have changed whether or not the install went through.
+    paging::forget(&addr, None);
+
+    let finished = result
+        .as_ref()
+        .map(|_| ())
+        .map_err(|err| err.message.clone());
+    let _ = progress_tx.try_send(ProgressUpdate::Finished(finished));
+    drop(progress_tx);
+    result.map_err(JsValue::from)?;
+    let duration_ms = js_sys::Date::now() - started_at;
+    record_install_rate(&addr, total_bytes, duration_ms);
+    Ok(InstallResult {
+        installed: true,
+        package_name: resolved_name,
+        assigned_id,
+        bytes_sent: total_bytes,
+        duration_ms,
+        verified: false,
+        activated: None,
+        activation_error: None,
+    })
+}
+
+/// Stops the install currently running on `addr`; resolves to `false` when
+/// there was nothing to cancel. The device is not told to abort and keeps
+/// waiting for parts until its own session times out, so new installs on
+/// `addr` reject with `DeviceBusy` for 30 seconds or until it reconnects.
+#[wasm_bindgen]
+pub fn device_cancel_install(addr: String) -> bool {
+    let handle = CANCEL_HANDLES.with(|cell| cell.borrow_mut().remove(&addr));
+    match handle {
+        Some((_, tx)) => tx.send(()).is_ok(),
+        None => false,
+    }
+}
+
+/// Progress of the install currently running on `addr` as
+/// `{ phase, percent, bytes_sent, total }`, or `null` when there is none, so
+/// a view that lost its `progress_cb` can pick the transfer back up.
+#[wasm_bindgen]
+pub fn device_get_active_install(addr: String) -> Result<JsValue, JsValue> {
+    let install =
+        ACTIVE_INSTALLS.with(|cell| cell.borrow().get(&addr).map(|(_, install)| *install));
+    match install {
+        Some(install) => {
+            to_js_value(&install).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+        }
+        None => Ok(JsValue::NULL),
+    }
+}
+
+/// Number of installs waiting behind the one currently running on `addr`.
+#[wasm_bindgen]
+pub fn device_install_queue_length(addr: String) -> usize {
+    QUEUED_INSTALLS.with(|cell| cell.borrow().get(&addr).copied().unwrap_or(0))
+}
+
+/// `ThirdpartyApp` -> `thirdparty_app`; a run of capitals is one word, so
+/// `OTAFirmware` -> `ota_firmware`.
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (idx, ch) in chars.iter().enumerate() {
+        if ch.is_ascii_uppercase() {
+            let prev = idx.checked_sub(1).map(|prev| chars[prev]);
+            let next = chars.get(idx + 1);
+            let word_start = prev.is_some_and(|prev| {
+                prev.is_ascii_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_ascii_uppercase() && next.is_some_and(char::is_ascii_lowercase))
+            });
+            if word_start {
+                out.push('_');
+            }
+            out.push(ch.to_ascii_lowercase());
+        } else {
+            out.push(*ch);
+        }
+    }
+    out
+}
+
+/// Maps every `MassDataType` corelib accepts to its `res_type` byte, keyed by
+/// the snake_case variant name, e.g. `{ watchface: 16, ... }`.
+#[wasm_bindgen]
+pub fn device_mass_data_types() -> JsValue {
+    let types = Object::new();
+    for value in 0..=u8::MAX {
+        if let Ok(data_type) = MassDataType::try_from(value) {
+            let _ = Reflect::set(
+                &types,
+                &JsValue::from_str(&snake_case(&format!("{:?}", data_type))),
+                &JsValue::from(value),
+            );
+        }
+    }
+    types.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn snake_case_keeps_acronyms_together() {
+        assert_eq!(snake_case("Watchface"), "watchface");
+        assert_eq!(snake_case("ThirdpartyApp"), "thirdparty_app");
+        assert_eq!(snake_case("OTA"), "ota");
+        assert_eq!(snake_case("OTAFirmware"), "ota_firmware");
+        assert_eq!(snake_case("FirmwareOTA"), "firmware_ota");
+    }
+
+    #[wasm_bindgen_test]
+    fn throttle_spaces_updates_by_interval() {
+        let mut throttle = ProgressThrottle::new(Some(100), None);
+        let phase = InstallPhase::Transferring;
+        assert!(throttle.should_forward(false, phase, 0.0, 0.0));
+        assert!(!throttle.should_forward(false, phase, 5.0, 50.0));
+        assert!(throttle.should_forward(false, phase, 6.0, 100.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn throttle_waits_for_min_percent() {
+        let mut throttle = ProgressThrottle::new(None, Some(1.0));
+        let phase = InstallPhase::Transferring;
+        assert!(throttle.should_forward(false, phase, 0.0, 0.0));
+        assert!(!throttle.should_forward(false, phase, 0.5, 10.0));
+        assert!(throttle.should_forward(false, phase, 1.2, 20.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn throttle_always_passes_phase_changes_and_the_end() {
+        let mut throttle = ProgressThrottle::new(Some(1_000), Some(10.0));
+        assert!(throttle.should_forward(false, InstallPhase::Transferring, 0.0, 0.0));
+        assert!(throttle.should_forward(false, InstallPhase::Applying, 100.0, 1.0));
+        assert!(throttle.should_forward(true, InstallPhase::Completed, 100.0, 2.0));
+    }
+}
@@ -0,0 +1,94 @@
+use std::{cell::Cell, cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+
+use super::{
+    DEFAULT_REQUEST_TIMEOUT_MS, SESSIONS, await_result_receiver_timeout, emit_link_error,
+    handle_remote_disconnect, idle, install, poll_info_system,
+};
+use crate::error::DeviceError;
+use crate::spp::xiaomi::DisconnectReason;
+use crate::timer::sleep_ms;
+
+pub(super) const DEFAULT_KEEPALIVE_MAX_FAILURES: u32 = 3;
+
+thread_local! {
+    static NEXT_KEEPALIVE_ID: Cell<u32> = const { Cell::new(0) };
+    static KEEPALIVES: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+fn keepalive_active(addr: &str, id: u32) -> bool {
+    KEEPALIVES.with(|cell| cell.borrow().get(addr) == Some(&id))
+        && SESSIONS.with(|cell| cell.borrow().contains_key(addr))
+}
+
+async fn ping(addr: &str) -> Result<(), DeviceError> {
+    idle::probe(addr, async {
+        let rx = poll_info_system(addr, |sys| Ok(sys.request_device_status()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver_timeout(
+            rx,
+            "Keepalive status response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await
+        .map(|_| ())
+    })
+    .await
+}
+
+/// Polls the device status every `interval_ms` and drops the session once
+/// `max_failures` pings in a row fail, for links that die without the read
+/// loop ever seeing the stream end. An interval of 0 disables it. Pings are
+/// skipped while an install holds the link, since status requests queue
+/// behind the transfer and would count as failures.
+pub(super) fn start_keepalive(addr: String, interval_ms: u32, max_failures: u32) {
+    if interval_ms == 0 {
+        return;
+    }
+    let id = NEXT_KEEPALIVE_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    KEEPALIVES.with(|cell| {
+        cell.borrow_mut().insert(addr.clone(), id);
+    });
+
+    let max_failures = max_failures.max(1);
+    spawn_local(async move {
+        let mut failures = 0;
+        loop {
+            sleep_ms(interval_ms).await;
+            if !keepalive_active(&addr, id) {
+                break;
+            }
+            if install::install_running(&addr) {
+                failures = 0;
+                continue;
+            }
+            match ping(&addr).await {
+                Ok(()) => failures = 0,
+                Err(err) => {
+                    failures += 1;
+                    web_sys::console::warn_1(&JsValue::from_str(&format!(
+                        "[wasm] Keepalive for {} failed ({}/{}): {}",
+                        addr, failures, max_failures, err
+                    )));
+                    if failures >= max_failures && keepalive_active(&addr, id) {
+                        emit_link_error(&addr, "keepalive", &err.message);
+                        handle_remote_disconnect(addr.clone(), DisconnectReason::Timeout).await;
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub(super) fn stop_keepalive(addr: &str) {
+    KEEPALIVES.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+}
@@ -0,0 +1,95 @@
+use js_sys::{Function, Object, Reflect};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::str::FromStr;
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use crate::error::{DeviceError, ErrorCode};
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+thread_local! {
+    static LOG_SINK: RefCell<Option<Function>> = RefCell::new(None);
+}
+
+/// Writes to the browser console and mirrors every record to the sink set
+/// through `device_set_log_sink`, if any.
+struct ForwardingLogger;
+
+impl Log for ForwardingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let line = JsValue::from_str(&format!("[{}] {}", record.target(), message));
+        match record.level() {
+            Level::Error => web_sys::console::error_1(&line),
+            Level::Warn => web_sys::console::warn_1(&line),
+            Level::Info => web_sys::console::info_1(&line),
+            Level::Debug | Level::Trace => web_sys::console::debug_1(&line),
+        }
+
+        let Some(sink) = LOG_SINK.with(|cell| cell.borrow().clone()) else {
+            return;
+        };
+        let entry = Object::new();
+        let _ = Reflect::set(
+            &entry,
+            &JsValue::from_str("level"),
+            &JsValue::from_str(&record.level().as_str().to_ascii_lowercase()),
+        );
+        let _ = Reflect::set(
+            &entry,
+            &JsValue::from_str("target"),
+            &JsValue::from_str(record.target()),
+        );
+        let _ = Reflect::set(
+            &entry,
+            &JsValue::from_str("message"),
+            &JsValue::from_str(&message),
+        );
+        // Logging from inside the sink would recurse, so failures only go to the console.
+        if let Err(err) = sink.call1(&JsValue::NULL, &entry) {
+            web_sys::console::error_2(&JsValue::from_str("log sink failed"), &err);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ForwardingLogger = ForwardingLogger;
+
+pub(super) fn init_logger() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(DEFAULT_LEVEL);
+    }
+}
+
+/// Accepts `off`, `error`, `warn`, `info`, `debug` or `trace`.
+#[wasm_bindgen]
+pub fn device_set_log_level(level: String) -> Result<(), JsValue> {
+    super::ensure_core_initialized();
+    let filter = LevelFilter::from_str(level.trim()).map_err(|_| {
+        DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown log level: {level}"),
+        )
+    })?;
+    log::set_max_level(filter);
+    Ok(())
+}
+
+/// Forwards log records as `{ level, target, message }`; pass `null` to stop.
+#[wasm_bindgen]
+pub fn device_set_log_sink(callback: Option<Function>) {
+    super::ensure_core_initialized();
+    LOG_SINK.with(|cell| {
+        *cell.borrow_mut() = callback;
+    });
+}
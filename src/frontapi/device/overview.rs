@@ -0,0 +1,86 @@
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::{
+    DEFAULT_REQUEST_TIMEOUT_MS, await_result_receiver_timeout, ensure_core_initialized,
+    with_info_system, with_resource_system,
+};
+use crate::error::{DeviceError, ErrorCode};
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Section<T> {
+    Loaded(T),
+    Failed { error: String, code: ErrorCode },
+}
+
+impl<T> From<Result<T, DeviceError>> for Section<T> {
+    fn from(result: Result<T, DeviceError>) -> Self {
+        match result {
+            Ok(value) => Self::Loaded(value),
+            Err(err) => Self::Failed {
+                error: err.message,
+                code: err.code,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Overview<I, W, A> {
+    info: Section<I>,
+    watchfaces: Section<W>,
+    apps: Section<A>,
+}
+
+/// Fetches device info, the watchface list and the quick app list at once.
+/// A section that fails or gets no answer within the request timeout is
+/// reported as `{ error, code }` in place of its data.
+#[wasm_bindgen]
+pub async fn device_get_overview(addr: String) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+
+    let info = async {
+        let rx = with_info_system(&addr, |sys| Ok(sys.request_device_info()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver_timeout(
+            rx,
+            "Device info response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await
+    };
+    let watchfaces = async {
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_watchface_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver_timeout(
+            rx,
+            "Watchface list response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await
+    };
+    let apps = async {
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_quick_app_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver_timeout(
+            rx,
+            "Quick app list response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await
+    };
+
+    let (info, watchfaces, apps) = tokio::join!(info, watchfaces, apps);
+    let overview = Overview {
+        info: info.into(),
+        watchfaces: watchfaces.into(),
+        apps: apps.into(),
+    };
+    to_js_value(&overview).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
@@ -0,0 +1,62 @@
+use corelib::device::xiaomi::resutils::FileType;
+use js_sys::Uint8Array;
+use serde::Serialize;
+use serde_json::Value;
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::io::{Cursor, Read};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::detect_file_type;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackageInfo {
+    pub file_type: u8,
+    pub package_name: Option<String>,
+    pub version: Option<String>,
+    pub min_firmware: Option<String>,
+    pub display_name: Option<String>,
+}
+
+/// Reads the quick app style `manifest.json` bundled in zip based packages.
+fn read_manifest(data: &[u8]) -> Option<Value> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+    let mut entry = archive.by_name("manifest.json").ok()?;
+    let mut raw = String::new();
+    entry.read_to_string(&mut raw).ok()?;
+    serde_json::from_str(&raw).ok()
+}
+
+fn manifest_string(manifest: &Value, key: &str) -> Option<String> {
+    match manifest.get(key)? {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        Value::Number(num) => Some(num.to_string()),
+        _ => None,
+    }
+}
+
+pub(super) fn parse_package(data: &[u8], name: &str) -> PackageInfo {
+    let file_type = detect_file_type(data, name);
+    let mut info = PackageInfo {
+        file_type: file_type as u8,
+        ..Default::default()
+    };
+
+    if matches!(file_type, FileType::Zip | FileType::Abp)
+        && let Some(manifest) = read_manifest(data)
+    {
+        info.package_name = manifest_string(&manifest, "package");
+        info.version = manifest_string(&manifest, "versionName")
+            .or_else(|| manifest_string(&manifest, "versionCode"));
+        info.min_firmware = manifest_string(&manifest, "minPlatformVersion");
+        info.display_name = manifest_string(&manifest, "name");
+    }
+
+    info
+}
+
+#[wasm_bindgen]
+pub async fn device_parse_package(file: Uint8Array, name: String) -> Result<JsValue, JsValue> {
+    let info = parse_package(&file.to_vec(), &name);
+    to_js_value(&info).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
@@ -0,0 +1,99 @@
+use js_sys::{Array, Object, Reflect};
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::{cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+
+thread_local! {
+    /// Last full list fetched per `(addr, kind)`, served to later pages.
+    static LISTS: RefCell<HashMap<(String, &'static str), Array>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The list protocol has no ranged queries, so pages are sliced from the
+/// full list that the first page (`offset` 0) fetched.
+pub(super) fn cached(addr: &str, kind: &'static str, offset: usize) -> Option<Array> {
+    if offset == 0 {
+        return None;
+    }
+    LISTS.with(|cell| cell.borrow().get(&(addr.to_string(), kind)).cloned())
+}
+
+/// Serializes `list` the way unpaged results are, so both forms match.
+pub(super) fn store<T: Serialize>(
+    addr: &str,
+    kind: &'static str,
+    list: &T,
+) -> Result<Array, JsValue> {
+    let items =
+        Array::from(&to_js_value(list).map_err(|err| JsValue::from_str(&format!("{:?}", err)))?);
+    LISTS.with(|cell| {
+        cell.borrow_mut()
+            .insert((addr.to_string(), kind), items.clone());
+    });
+    Ok(items)
+}
+
+/// Drops the cached lists of `addr`, only those of `kind` when given. Called
+/// whenever a command changes what the device would list.
+pub(super) fn forget(addr: &str, kind: Option<&'static str>) {
+    LISTS.with(|cell| {
+        cell.borrow_mut().retain(|(entry_addr, entry_kind), _| {
+            entry_addr != addr || kind.is_some_and(|kind| kind != *entry_kind)
+        });
+    });
+}
+
+/// `start..end` of the page at `offset`; without a limit it runs to the end.
+fn page_bounds(len: usize, offset: usize, limit: Option<usize>) -> (usize, usize) {
+    let start = offset.min(len);
+    let end = limit.map_or(len, |limit| start.saturating_add(limit).min(len));
+    (start, end)
+}
+
+/// `{ items, total, has_more }` for `limit` items from `offset`.
+pub(super) fn page(items: &Array, offset: usize, limit: Option<usize>) -> Result<JsValue, JsValue> {
+    let total = items.length() as usize;
+    let (start, end) = page_bounds(total, offset, limit);
+    let page = Object::new();
+    Reflect::set(
+        &page,
+        &JsValue::from_str("items"),
+        &items.slice(start as u32, end as u32),
+    )?;
+    Reflect::set(&page, &JsValue::from_str("total"), &JsValue::from(total))?;
+    Reflect::set(
+        &page,
+        &JsValue::from_str("has_more"),
+        &JsValue::from_bool(end < total),
+    )?;
+    Ok(page.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn bounds_clamp_to_the_list() {
+        assert_eq!(page_bounds(10, 0, Some(4)), (0, 4));
+        assert_eq!(page_bounds(10, 8, Some(4)), (8, 10));
+        assert_eq!(page_bounds(10, 12, Some(4)), (10, 10));
+        assert_eq!(page_bounds(10, 3, None), (3, 10));
+        assert_eq!(page_bounds(10, 3, Some(usize::MAX)), (3, 10));
+    }
+
+    #[wasm_bindgen_test]
+    fn pages_report_total_and_has_more() {
+        let items = Array::of3(&1.into(), &2.into(), &3.into());
+        let page = page(&items, 1, Some(1)).unwrap();
+        let field = |key: &str| Reflect::get(&page, &JsValue::from_str(key)).unwrap();
+        assert_eq!(
+            Array::from(&field("items")).to_vec(),
+            vec![JsValue::from(2)]
+        );
+        assert_eq!(field("total"), JsValue::from(3));
+        assert_eq!(field("has_more"), JsValue::TRUE);
+    }
+}
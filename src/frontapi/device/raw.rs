@@ -0,0 +1,97 @@
+use js_sys::{Function, Object, Reflect, Uint8Array};
+use std::{cell::RefCell, collections::HashSet};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{JsCast, JsValue};
+
+use super::{SESSIONS, emit_event, ensure_core_initialized, idle};
+use crate::error::{DeviceError, ErrorCode};
+use crate::spp::xiaomi::WriteCommand;
+
+thread_local! {
+    static RAW_CAPTURE: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    static INTERCEPTOR: RefCell<Option<Function>> = const { RefCell::new(None) };
+}
+
+fn emit_raw_packet(addr: &str, direction: &str, data: &[u8]) {
+    let payload = Object::new();
+    let _ = Reflect::set(
+        &payload,
+        &JsValue::from_str("direction"),
+        &JsValue::from_str(direction),
+    );
+    let _ = Reflect::set(
+        &payload,
+        &JsValue::from_str("data"),
+        &Uint8Array::from(data),
+    );
+    emit_event("raw-packet", addr, &payload);
+}
+
+pub(super) fn capture_inbound(addr: &str, data: &[u8]) {
+    if RAW_CAPTURE.with(|cell| cell.borrow().contains(addr)) {
+        emit_raw_packet(addr, "in", data);
+    }
+}
+
+/// Runs the interceptor on a chunk about to be written. A throw or anything
+/// other than a `Uint8Array` leaves the chunk as it was.
+pub(super) fn intercept_outbound(addr: &str, data: Vec<u8>) -> Vec<u8> {
+    let Some(interceptor) = INTERCEPTOR.with(|cell| cell.borrow().clone()) else {
+        return data;
+    };
+    match interceptor.call2(
+        &JsValue::NULL,
+        &Uint8Array::from(data.as_slice()),
+        &JsValue::from_str(addr),
+    ) {
+        Ok(result) => match result.dyn_ref::<Uint8Array>() {
+            Some(bytes) => bytes.to_vec(),
+            None => data,
+        },
+        Err(err) => {
+            web_sys::console::error_2(&JsValue::from_str("packet interceptor failed"), &err);
+            data
+        }
+    }
+}
+
+/// Calls `callback(data, addr)` with every chunk right before it is written
+/// to any device. Returning a `Uint8Array` sends that instead; `null` sends
+/// the chunk unchanged. Pass `null` to remove the interceptor.
+#[wasm_bindgen]
+pub fn device_set_packet_interceptor(callback: Option<Function>) {
+    INTERCEPTOR.with(|cell| {
+        *cell.borrow_mut() = callback;
+    });
+}
+
+#[wasm_bindgen]
+pub fn device_enable_raw_capture(addr: String, enabled: bool) {
+    RAW_CAPTURE.with(|cell| {
+        let mut capture = cell.borrow_mut();
+        if enabled {
+            capture.insert(addr);
+        } else {
+            capture.remove(&addr);
+        }
+    });
+}
+
+/// Writes `data` to the device as-is, bypassing corelib's packet builders.
+#[wasm_bindgen]
+pub async fn device_send_raw(addr: String, data: Uint8Array) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    let sender = SESSIONS
+        .with(|cell| cell.borrow().get(&addr).and_then(|s| s.write_sender()))
+        .ok_or_else(|| DeviceError::new(ErrorCode::DeviceNotFound, "Device not found"))?;
+
+    idle::touch(&addr);
+    let bytes = data.to_vec();
+    if RAW_CAPTURE.with(|cell| cell.borrow().contains(&addr)) {
+        emit_raw_packet(&addr, "out", &bytes);
+    }
+    sender
+        .send(WriteCommand::Data(bytes))
+        .await
+        .map_err(|_| DeviceError::new(ErrorCode::DeviceNotFound, "Serial writer closed").into())
+}
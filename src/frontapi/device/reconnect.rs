@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use serde_wasm_bindgen::from_value as from_js_value;
+use std::{cell::Cell, cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use super::{
+    SESSIONS, emit_connection_state, ensure_core_initialized, has_known_port, notify_disconnected,
+    reconnect_known,
+};
+use crate::error::{DeviceError, ErrorCode};
+use crate::spp::xiaomi::{ConnectionState, DisconnectReason};
+use crate::timer::sleep_ms;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_BASE_DELAY_MS: u32 = 1_000;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+struct ReconnectOptions {
+    max_attempts: Option<u32>,
+    base_delay_ms: Option<u32>,
+}
+
+#[derive(Clone, Copy)]
+struct ReconnectPolicy {
+    max_attempts: u32,
+    base_delay_ms: u32,
+}
+
+thread_local! {
+    static POLICIES: RefCell<HashMap<String, ReconnectPolicy>> = RefCell::new(HashMap::new());
+    static NEXT_RUN_ID: Cell<u32> = const { Cell::new(0) };
+    /// Id of the reconnect loop currently running per address.
+    static RUNNING: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+fn still_running(addr: &str, id: u32) -> bool {
+    RUNNING.with(|cell| cell.borrow().get(addr) == Some(&id))
+}
+
+/// Stops a pending reconnect loop for `addr`, e.g. when the user disconnects.
+pub(super) fn cancel(addr: &str) {
+    RUNNING.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+}
+
+/// Starts reopening `addr` in the background if auto reconnect is enabled
+/// for it. Disconnects the host or this crate asked for are left alone.
+pub(super) fn schedule(addr: String, reason: DisconnectReason) {
+    if matches!(
+        reason,
+        DisconnectReason::User
+            | DisconnectReason::Idle
+            | DisconnectReason::Reconnecting
+            | DisconnectReason::ReconnectFailed
+    ) {
+        return;
+    }
+    let Some(policy) = POLICIES.with(|cell| cell.borrow().get(&addr).copied()) else {
+        return;
+    };
+    let id = NEXT_RUN_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    RUNNING.with(|cell| {
+        cell.borrow_mut().insert(addr.clone(), id);
+    });
+
+    spawn_local(async move {
+        // Only serial ports are cached; without one there is nothing to retry with.
+        let attempts = if has_known_port(&addr) {
+            policy.max_attempts
+        } else {
+            0
+        };
+        let mut delay_ms = policy.base_delay_ms;
+        for attempt in 1..=attempts {
+            emit_connection_state(&addr, ConnectionState::Reconnecting);
+            sleep_ms(delay_ms).await;
+            if !still_running(&addr, id) || SESSIONS.with(|cell| cell.borrow().contains_key(&addr))
+            {
+                return;
+            }
+            match reconnect_known(addr.clone()).await {
+                Ok(_) => {
+                    cancel(&addr);
+                    return;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "[wasm] Reconnect to {} failed ({}/{}): {:?}",
+                        addr,
+                        attempt,
+                        policy.max_attempts,
+                        err
+                    );
+                }
+            }
+            delay_ms = delay_ms.saturating_mul(2);
+        }
+        if still_running(&addr, id) {
+            cancel(&addr);
+            notify_disconnected(addr, DisconnectReason::ReconnectFailed).await;
+        }
+    });
+}
+
+/// Reopens the cached port of `addr` after it drops, waiting `base_delay_ms`
+/// (1s by default) and doubling the wait for each of up to `max_attempts`
+/// tries (5 by default).
+///
+/// A drop still emits `device-disconnected` right away with its own reason,
+/// e.g. `remote` or `error`. `connection-state-changed` then reports
+/// `Reconnecting` before each try, and if none succeeds a second
+/// `device-disconnected` with reason `reconnect-failed` follows the last one.
+#[wasm_bindgen]
+pub fn device_enable_auto_reconnect(addr: String, options: JsValue) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    let options = from_js_value::<Option<ReconnectOptions>>(options)
+        .map_err(|err| {
+            DeviceError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid reconnect options: {}", err),
+            )
+        })?
+        .unwrap_or_default();
+    let policy = ReconnectPolicy {
+        max_attempts: options.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+        base_delay_ms: options.base_delay_ms.unwrap_or(DEFAULT_BASE_DELAY_MS),
+    };
+    POLICIES.with(|cell| {
+        cell.borrow_mut().insert(addr, policy);
+    });
+    Ok(())
+}
+
+/// Turns auto reconnect off for `addr` and stops a reconnect in progress.
+#[wasm_bindgen]
+pub fn device_disable_auto_reconnect(addr: String) {
+    POLICIES.with(|cell| {
+        cell.borrow_mut().remove(&addr);
+    });
+    cancel(&addr);
+}
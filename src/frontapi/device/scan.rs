@@ -0,0 +1,85 @@
+use serde::Serialize;
+use serde_wasm_bindgen::to_value as to_js_value;
+use std::cell::RefCell;
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::{emit_event, ensure_core_initialized, list_granted_ports};
+use crate::ble::scan::BleScan;
+use crate::error::{DeviceError, ErrorCode};
+
+thread_local! {
+    static ACTIVE_SCAN: RefCell<Option<BleScan>> = const { RefCell::new(None) };
+}
+
+#[derive(Serialize)]
+struct FoundDevice<'a> {
+    transport: &'a str,
+    name: Option<String>,
+    id: String,
+    rssi: Option<i8>,
+    /// Set for serial ports, to pass to `device_connect_known`.
+    port_id: Option<u32>,
+}
+
+fn emit_found(device: FoundDevice) {
+    if let Ok(payload) = to_js_value(&device) {
+        let id = device.id.clone();
+        emit_event("device-found", &id, &payload);
+    }
+}
+
+/// Emits `device-found` with `{ transport, name, id, rssi, port_id }` for
+/// nearby devices. BLE keeps reporting advertisements until
+/// `device_stop_scan`; serial has nothing to scan, so it reports each granted
+/// port once and returns.
+#[wasm_bindgen]
+pub async fn device_start_scan(transport: Option<String>) -> Result<(), JsValue> {
+    ensure_core_initialized();
+    match transport.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("ble") => {
+            device_stop_scan();
+            let scan = BleScan::start(Box::new(|name, id, rssi| {
+                emit_found(FoundDevice {
+                    transport: "ble",
+                    name,
+                    id: format!("ble:{id}"),
+                    rssi,
+                    port_id: None,
+                });
+            }))
+            .await?;
+            ACTIVE_SCAN.with(|cell| *cell.borrow_mut() = Some(scan));
+            Ok(())
+        }
+        Some("spp" | "serial") => {
+            for port in list_granted_ports().await? {
+                let name = port.serial_number.clone().or_else(|| {
+                    port.vendor_id
+                        .zip(port.product_id)
+                        .map(|(v, p)| format!("USB {:04x}:{:04x}", v, p))
+                });
+                emit_found(FoundDevice {
+                    transport: "serial",
+                    name,
+                    id: format!("port:{}", port.port_id),
+                    rssi: None,
+                    port_id: Some(port.port_id),
+                });
+            }
+            Ok(())
+        }
+        Some(other) => Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown scan transport: {other}"),
+        )
+        .into()),
+    }
+}
+
+#[wasm_bindgen]
+pub fn device_stop_scan() {
+    if let Some(scan) = ACTIVE_SCAN.with(|cell| cell.borrow_mut().take()) {
+        scan.stop();
+    }
+}
@@ -0,0 +1,142 @@
+use corelib::device::xiaomi::components::info::DeviceStorage;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
+
+use super::{
+    DEFAULT_REQUEST_TIMEOUT_MS, await_result_receiver_timeout, ensure_core_initialized,
+    with_info_system, with_resource_system,
+};
+use crate::error::{DeviceError, ErrorCode};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StorageSummary {
+    pub total: Option<u64>,
+    pub used: Option<u64>,
+    pub free: Option<u64>,
+}
+
+/// The list responses carry no sizes, so only the number of items is known.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CategoryUsage {
+    pub count: Option<usize>,
+}
+
+/// Each part is fetched independently; parts that failed, timed out or were
+/// not reported by the firmware are named in `missing` (`storage`,
+/// `watchfaces`, `apps`) instead of failing the whole request.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageDetail {
+    pub total: Option<u64>,
+    pub used: Option<u64>,
+    pub free: Option<u64>,
+    pub watchfaces: CategoryUsage,
+    pub apps: CategoryUsage,
+    pub music: CategoryUsage,
+    pub missing: Vec<String>,
+}
+
+/// Extracts the aggregate figures from a device storage response. Firmware
+/// that does not track storage reports a zero total, which yields no figures.
+pub(super) fn storage_summary(storage: &DeviceStorage) -> StorageSummary {
+    if storage.total == 0 {
+        return StorageSummary::default();
+    }
+    StorageSummary {
+        total: Some(storage.total),
+        used: Some(storage.used),
+        free: Some(storage.total.saturating_sub(storage.used)),
+    }
+}
+
+/// Free bytes on the device, `None` when the firmware does not report enough
+/// to derive them.
+pub(super) async fn free_storage(addr: &str) -> Result<Option<u64>, DeviceError> {
+    let rx = with_info_system(addr, |sys| Ok(sys.request_device_storage()))
+        .await
+        .map_err(DeviceError::from_core)?;
+    let storage = await_result_receiver_timeout(
+        rx,
+        "Device storage response not received",
+        DEFAULT_REQUEST_TIMEOUT_MS,
+    )
+    .await?;
+    Ok(storage_summary(&storage).free)
+}
+
+/// Available bytes on the device, for gating installs without reading the
+/// whole storage response.
+#[wasm_bindgen]
+pub async fn device_get_free_storage(addr: String) -> Result<u64, JsValue> {
+    ensure_core_initialized();
+    free_storage(&addr).await?.ok_or_else(|| {
+        DeviceError::new(
+            ErrorCode::Unsupported,
+            "Device did not report its free storage",
+        )
+        .into()
+    })
+}
+
+fn category_usage<T>(list: Option<&[T]>) -> CategoryUsage {
+    CategoryUsage {
+        count: list.map(<[T]>::len),
+    }
+}
+
+pub(super) async fn storage_detail(addr: &str) -> Result<StorageDetail, DeviceError> {
+    let storage_rx = with_info_system(addr, |sys| Ok(sys.request_device_storage()))
+        .await
+        .map_err(DeviceError::from_core)?;
+    let watchface_rx = with_resource_system(addr, |sys| Ok(sys.request_watchface_list())).await;
+    let app_rx = with_resource_system(addr, |sys| Ok(sys.request_quick_app_list())).await;
+
+    let (storage, watchfaces, apps) = tokio::join!(
+        await_result_receiver_timeout(
+            storage_rx,
+            "Device storage response not received",
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        ),
+        async {
+            await_result_receiver_timeout(
+                watchface_rx.map_err(DeviceError::from_core)?,
+                "Watchface list response not received",
+                DEFAULT_REQUEST_TIMEOUT_MS,
+            )
+            .await
+        },
+        async {
+            await_result_receiver_timeout(
+                app_rx.map_err(DeviceError::from_core)?,
+                "Quick app list response not received",
+                DEFAULT_REQUEST_TIMEOUT_MS,
+            )
+            .await
+        },
+    );
+
+    if let (Err(err), Err(_), Err(_)) = (&storage, &watchfaces, &apps) {
+        return Err(err.clone());
+    }
+    let summary = storage.as_ref().map(storage_summary).unwrap_or_default();
+    let mut missing = Vec::new();
+    for (part, failed) in [
+        ("storage", summary.total.is_none()),
+        ("watchfaces", watchfaces.is_err()),
+        ("apps", apps.is_err()),
+    ] {
+        if failed {
+            missing.push(part.to_string());
+        }
+    }
+
+    Ok(StorageDetail {
+        total: summary.total,
+        used: summary.used,
+        free: summary.free,
+        watchfaces: category_usage(watchfaces.ok().as_deref()),
+        apps: category_usage(apps.ok().as_deref()),
+        music: CategoryUsage::default(),
+        missing,
+    })
+}
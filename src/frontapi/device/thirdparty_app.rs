@@ -1,88 +1,361 @@
 use corelib::device::xiaomi::components::thirdparty_app::AppInfo;
-use serde_wasm_bindgen::to_value as to_js_value;
-use wasm_bindgen::JsValue;
+use serde::Serialize;
+use serde_wasm_bindgen::{from_value as from_js_value, to_value as to_js_value};
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::{JsCast, JsValue};
 
+use super::events::with_list_loading;
+use super::paging;
 use super::{
-    await_result_receiver, ensure_core_initialized, with_resource_component, with_resource_system,
-    with_thirdparty_app_system,
+    ItemResult, await_result_receiver, ensure_core_initialized, serialized,
+    with_resource_component, with_resource_system, with_thirdparty_app_system,
 };
+use crate::error::{DeviceError, ErrorCode};
 
+/// Resolves with the full list, or with `{ items, total, has_more }` when
+/// `offset` or `limit` is given.
 #[wasm_bindgen]
-pub async fn thirdpartyapp_get_list(addr: String) -> Result<JsValue, JsValue> {
+pub async fn thirdpartyapp_get_list(
+    addr: String,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> Result<JsValue, JsValue> {
     ensure_core_initialized();
-    let rx = with_resource_system(&addr, |sys| Ok(sys.request_quick_app_list()))
-        .await
-        .map_err(|err| JsValue::from_str(&err))?;
-    let list = await_result_receiver(rx, "Quick app list response not received").await?;
+    let paged = offset.is_some() || limit.is_some();
+    let offset = offset.unwrap_or(0) as usize;
+    let limit = limit.map(|limit| limit as usize);
+    if let Some(items) = paging::cached(&addr, "quick_apps", offset) {
+        return paging::page(&items, offset, limit);
+    }
+
+    let list = with_list_loading(&addr, "quick_apps", async {
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_quick_app_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver(rx, "Quick app list response not received")
+            .await
+            .map_err(JsValue::from)
+    })
+    .await?;
+    if paged {
+        let items = paging::store(&addr, "quick_apps", &list)?;
+        return paging::page(&items, offset, limit);
+    }
     to_js_value(&list).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
 }
 
+/// Returns the cached entry for one installed quick app, as last reported by
+/// `thirdpartyapp_get_list`.
 #[wasm_bindgen]
-pub async fn thirdpartyapp_send_message(
+pub async fn thirdpartyapp_get_info(
     addr: String,
     package_name: String,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let target = package_name.clone();
+    let entry = with_resource_component(&addr, move |comp| {
+        Ok(comp
+            .quick_apps
+            .iter()
+            .find(|item| item.package_name == target)
+            .and_then(|item| serde_json::to_value(item).ok()))
+    })
+    .await
+    .map_err(DeviceError::from_core)?
+    .ok_or_else(|| {
+        DeviceError::new(
+            ErrorCode::AppNotFound,
+            format!("AppInfo not found for {}", package_name),
+        )
+    })?;
+    entry
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// The app is picked by `package_name`, by its hex `fingerprint` (separators
+/// and case are ignored), or by both; at least one of them is required and an
+/// unknown app rejects with `AppNotFound`.
+#[wasm_bindgen]
+pub async fn thirdpartyapp_send_message(
+    addr: String,
+    package_name: Option<String>,
     data: String,
+    fingerprint: Option<String>,
 ) -> Result<(), JsValue> {
     ensure_core_initialized();
-    let info = get_app_info(&addr, &package_name).await?;
+    let info = get_app_info(&addr, package_name, fingerprint).await?;
     let payload = data.into_bytes();
-    with_thirdparty_app_system(&addr, move |sys| {
-        sys.send_phone_message(&info, payload);
-        Ok(())
-    })
+    serialized(
+        &addr,
+        with_thirdparty_app_system(&addr, move |sys| {
+            sys.send_phone_message(&info, payload);
+            Ok(())
+        }),
+    )
     .await
-    .map_err(|err| JsValue::from_str(&err))
+    .map_err(|err| DeviceError::from_core(err).into())
 }
 
+/// Picks the app like `thirdpartyapp_send_message`.
 #[wasm_bindgen]
 pub async fn thirdpartyapp_launch(
     addr: String,
-    package_name: String,
+    package_name: Option<String>,
     page: String,
+    fingerprint: Option<String>,
 ) -> Result<(), JsValue> {
     ensure_core_initialized();
-    let info = get_app_info(&addr, &package_name).await?;
-    with_thirdparty_app_system(&addr, move |sys| {
-        sys.launch_app(&info, &page);
-        Ok(())
-    })
+    let info = get_app_info(&addr, package_name, fingerprint).await?;
+    serialized(
+        &addr,
+        with_thirdparty_app_system(&addr, move |sys| {
+            sys.launch_app(&info, &page);
+            Ok(())
+        }),
+    )
     .await
-    .map_err(|err| JsValue::from_str(&err))
+    .map_err(|err| DeviceError::from_core(err).into())
+}
+
+/// Appends `params` to `page` as a query string, the form the quick app
+/// router reads launch parameters from. Nested values are JSON-encoded.
+fn page_with_params(page: &str, params: JsValue) -> Result<String, DeviceError> {
+    let invalid = || {
+        DeviceError::new(
+            ErrorCode::InvalidArgument,
+            "Launch params must be a plain object",
+        )
+    };
+    let Some(object) = params.dyn_ref::<js_sys::Object>() else {
+        return Err(invalid());
+    };
+    let proto = js_sys::Object::get_prototype_of(object);
+    if js_sys::Array::is_array(&params)
+        || !(proto.is_null() || proto == js_sys::Object::get_prototype_of(&js_sys::Object::new()))
+    {
+        return Err(invalid());
+    }
+    let params =
+        from_js_value::<serde_json::Map<String, serde_json::Value>>(params).map_err(|err| {
+            DeviceError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid launch params: {}", err),
+            )
+        })?;
+
+    let mut uri = page.to_string();
+    let mut separator = if page.contains('?') { '&' } else { '?' };
+    for (key, value) in params {
+        let value = match value {
+            serde_json::Value::String(text) => text,
+            other => other.to_string(),
+        };
+        uri.push(separator);
+        uri.push_str(&String::from(js_sys::encode_uri_component(&key)));
+        uri.push('=');
+        uri.push_str(&String::from(js_sys::encode_uri_component(&value)));
+        separator = '&';
+    }
+    Ok(uri)
 }
 
+/// Like `thirdpartyapp_launch`, passing `params` to the page. `launch_app`
+/// only carries a page string, so they travel as its query string.
 #[wasm_bindgen]
-pub async fn thirdpartyapp_uninstall(addr: String, package_name: String) -> Result<(), JsValue> {
+pub async fn thirdpartyapp_launch_with_params(
+    addr: String,
+    package_name: Option<String>,
+    page: String,
+    params: JsValue,
+    fingerprint: Option<String>,
+) -> Result<(), JsValue> {
     ensure_core_initialized();
-    let info = get_app_info(&addr, &package_name).await?;
-    with_thirdparty_app_system(&addr, move |sys| {
-        sys.uninstall_app(&info);
-        Ok(())
-    })
+    let page = page_with_params(&page, params)?;
+    let info = get_app_info(&addr, package_name, fingerprint).await?;
+    serialized(
+        &addr,
+        with_thirdparty_app_system(&addr, move |sys| {
+            sys.launch_app(&info, &page);
+            Ok(())
+        }),
+    )
     .await
-    .map_err(|err| JsValue::from_str(&err))?;
+    .map_err(|err| DeviceError::from_core(err).into())
+}
+
+#[derive(Serialize)]
+struct UninstallResult {
+    uninstalled: bool,
+    /// Always `null`: neither the app list nor the storage report says how
+    /// much an uninstall freed.
+    freed_bytes: Option<u64>,
+    apps: Option<serde_json::Value>,
+}
+
+/// Uninstalls an app and confirms it against a fresh app list, since the
+/// device doesn't acknowledge the uninstall itself. `include_list` adds the
+/// refreshed list as `apps`. The app is picked like in
+/// `thirdpartyapp_send_message`.
+#[wasm_bindgen]
+pub async fn thirdpartyapp_uninstall(
+    addr: String,
+    package_name: Option<String>,
+    fingerprint: Option<String>,
+    include_list: Option<bool>,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let info = get_app_info(&addr, package_name, fingerprint).await?;
+    let target = info.package_name.clone();
+    // Held through the confirming list so a later command can't change it.
+    let list = serialized(&addr, async {
+        with_thirdparty_app_system(&addr, move |sys| {
+            sys.uninstall_app(&info);
+            Ok(())
+        })
+        .await
+        .map_err(DeviceError::from_core)?;
+        paging::forget(&addr, Some("quick_apps"));
+
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_quick_app_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver(rx, "Quick app list response not received")
+            .await
+            .map_err(JsValue::from)
+    })
+    .await?;
+    let uninstalled = !list.iter().any(|item| item.package_name == target);
+    let result = UninstallResult {
+        uninstalled,
+        freed_bytes: None,
+        apps: include_list
+            .unwrap_or(false)
+            .then(|| serde_json::to_value(&list).ok())
+            .flatten(),
+    };
+    result
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// Uninstalls each package in turn and refreshes the app list once at the
+/// end, resolving with one `{ id, ok, error }` entry per package.
+#[wasm_bindgen]
+pub async fn thirdpartyapp_uninstall_many(
+    addr: String,
+    packages: Vec<String>,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let results = serialized(&addr, async {
+        let mut results = Vec::with_capacity(packages.len());
+        for package_name in packages {
+            let result = match get_app_info(&addr, Some(package_name.clone()), None).await {
+                Ok(info) => {
+                    with_thirdparty_app_system(&addr, move |sys| {
+                        sys.uninstall_app(&info);
+                        Ok(())
+                    })
+                    .await
+                }
+                Err(err) => Err(err.message),
+            };
+            results.push(ItemResult::new(package_name, result));
+        }
+        results
+    })
+    .await;
+    paging::forget(&addr, Some("quick_apps"));
 
     let _ = with_resource_system(&addr, |sys| {
-        let _ = sys.request_quick_app_list();
+        drop(sys.request_quick_app_list());
         Ok(())
     })
     .await;
 
-    Ok(())
+    to_js_value(&results).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+fn fingerprint_hex(fingerprint: &[u8]) -> String {
+    fingerprint
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
 }
 
-async fn get_app_info(addr: &str, package_name: &str) -> Result<AppInfo, JsValue> {
-    let target = package_name.to_string();
-    with_resource_component(addr, move |comp| {
-        comp.quick_apps
+/// Resolves an installed quick app by package name, by hex fingerprint, or by
+/// both, in which case they have to name the same app.
+async fn get_app_info(
+    addr: &str,
+    package_name: Option<String>,
+    fingerprint: Option<String>,
+) -> Result<AppInfo, DeviceError> {
+    let fingerprint = fingerprint.map(|fp| normalize_fingerprint(&fp));
+    if package_name.is_none() && fingerprint.is_none() {
+        return Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            "Either a package name or a fingerprint is required",
+        ));
+    }
+
+    let apps = with_resource_component(addr, |comp| {
+        Ok(comp
+            .quick_apps
             .iter()
-            .find(|item| item.package_name == target)
             .map(|item| AppInfo {
                 package_name: item.package_name.clone(),
                 fingerprint: item.fingerprint.clone(),
             })
-            .ok_or_else(|| format!("AppInfo not found for {}", target))
+            .collect::<Vec<_>>())
     })
     .await
-    .map_err(|err| JsValue::from_str(&err))
+    .map_err(DeviceError::from_core)?;
+
+    let by_name = package_name.as_ref().map(|target| {
+        apps.iter()
+            .position(|item| &item.package_name == target)
+            .ok_or_else(|| {
+                DeviceError::new(
+                    ErrorCode::AppNotFound,
+                    format!("AppInfo not found for {}", target),
+                )
+            })
+    });
+    let by_fingerprint = fingerprint.as_ref().map(|target| {
+        apps.iter()
+            .position(|item| fingerprint_hex(&item.fingerprint) == *target)
+            .ok_or_else(|| {
+                DeviceError::new(
+                    ErrorCode::AppNotFound,
+                    format!("AppInfo not found for fingerprint {}", target),
+                )
+            })
+    });
+
+    let index = match (by_name, by_fingerprint) {
+        (Some(name), Some(fp)) => {
+            let (name, fp) = (name?, fp?);
+            if name != fp {
+                return Err(DeviceError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Package name {} and fingerprint refer to different apps",
+                        package_name.unwrap_or_default()
+                    ),
+                ));
+            }
+            name
+        }
+        (Some(found), None) | (None, Some(found)) => found?,
+        (None, None) => unreachable!("checked above"),
+    };
+    Ok(apps.into_iter().nth(index).unwrap())
 }
@@ -1,41 +1,307 @@
+use corelib::device::xiaomi::components::resource::WatchfaceInfo;
+use corelib::device::xiaomi::packet::mass::MassDataType;
+use corelib::device::xiaomi::resutils::{FileType, get_file_type};
+use js_sys::{Function, Uint8Array};
 use serde_wasm_bindgen::to_value as to_js_value;
+use std::{cell::RefCell, collections::HashMap};
 use wasm_bindgen::JsValue;
 use wasm_bindgen::prelude::*;
 
+use super::events::with_list_loading;
+use super::install::{
+    InstallOptions, InstallResult, check_free_storage, transfer_package, with_install_slot,
+};
+use super::paging;
 use super::{
-    await_result_receiver, ensure_core_initialized, with_resource_system, with_watchface_system,
+    ItemResult, await_result_receiver, ensure_core_initialized, flush_writes, serialized,
+    with_resource_system, with_watchface_system,
 };
+use crate::error::{DeviceError, ErrorCode};
+
+thread_local! {
+    /// Face picked through this module per addr, with when it was picked.
+    static CURRENT_WATCHFACE: RefCell<HashMap<String, (String, f64)>> =
+        RefCell::new(HashMap::new());
+}
+
+/// How long a picked face is reported before the device's own list is asked
+/// again; long enough for the device to have applied the change.
+const CURRENT_OVERRIDE_MS: f64 = 5_000.0;
+
+fn current_from_list(list: &[WatchfaceInfo]) -> Option<String> {
+    list.iter()
+        .find(|entry| entry.active)
+        .map(|entry| entry.id.clone())
+}
+
+fn list_contains(list: &[WatchfaceInfo], id: &str) -> bool {
+    list.iter().any(|entry| entry.id == id)
+}
+
+async fn fetch_watchface_ids(addr: &str) -> Result<Vec<String>, DeviceError> {
+    let rx = with_resource_system(addr, |sys| Ok(sys.request_watchface_list()))
+        .await
+        .map_err(DeviceError::from_core)?;
+    let list = await_result_receiver(rx, "Watchface list response not received").await?;
+    Ok(list.into_iter().map(|entry| entry.id).collect())
+}
+
+fn remember_current(addr: &str, id: String) {
+    CURRENT_WATCHFACE.with(|cell| {
+        cell.borrow_mut()
+            .insert(addr.to_string(), (id, js_sys::Date::now()));
+    });
+}
+
+/// The face picked less than `CURRENT_OVERRIDE_MS` ago, if any.
+fn recent_current(addr: &str) -> Option<String> {
+    CURRENT_WATCHFACE.with(|cell| {
+        let mut tracked = cell.borrow_mut();
+        let (id, picked_at) = tracked.get(addr)?;
+        if js_sys::Date::now() - picked_at < CURRENT_OVERRIDE_MS {
+            return Some(id.clone());
+        }
+        tracked.remove(addr);
+        None
+    })
+}
+
+/// Drops the picked face once it is gone from the device, so it can't be
+/// reported after being uninstalled.
+fn forget_current_if(addr: &str, id: &str) {
+    CURRENT_WATCHFACE.with(|cell| {
+        let mut tracked = cell.borrow_mut();
+        if tracked.get(addr).is_some_and(|(current, _)| current == id) {
+            tracked.remove(addr);
+        }
+    });
+}
+
+pub(super) fn forget_current(addr: &str) {
+    CURRENT_WATCHFACE.with(|cell| {
+        cell.borrow_mut().remove(addr);
+    });
+}
+
+/// Resolves with the full list, or with `{ items, total, has_more }` when
+/// `offset` or `limit` is given.
+#[wasm_bindgen]
+pub async fn watchface_get_list(
+    addr: String,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let paged = offset.is_some() || limit.is_some();
+    let offset = offset.unwrap_or(0) as usize;
+    let limit = limit.map(|limit| limit as usize);
+    if let Some(items) = paging::cached(&addr, "watchfaces", offset) {
+        return paging::page(&items, offset, limit);
+    }
+
+    let list = with_list_loading(&addr, "watchfaces", async {
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_watchface_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        await_result_receiver(rx, "Watchface list response not received")
+            .await
+            .map_err(JsValue::from)
+    })
+    .await?;
+    if paged {
+        let items = paging::store(&addr, "watchfaces", &list)?;
+        return paging::page(&items, offset, limit);
+    }
+    to_js_value(&list).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
 
+/// Returns the id of the watchface on display, as the device lists it. For a
+/// few seconds after `watchface_set_current` the picked face is reported
+/// instead, since the device may not have updated its list yet.
 #[wasm_bindgen]
-pub async fn watchface_get_list(addr: String) -> Result<JsValue, JsValue> {
+pub async fn watchface_get_current(addr: String) -> Result<Option<String>, JsValue> {
     ensure_core_initialized();
+    if let Some(id) = recent_current(&addr) {
+        return Ok(Some(id));
+    }
+
     let rx = with_resource_system(&addr, |sys| Ok(sys.request_watchface_list()))
         .await
-        .map_err(|err| JsValue::from_str(&err))?;
+        .map_err(DeviceError::from_core)?;
     let list = await_result_receiver(rx, "Watchface list response not received").await?;
-    to_js_value(&list).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+    Ok(current_from_list(&list))
 }
 
+/// The device ignores ids it doesn't have, so the id is checked against a
+/// fresh watchface list first unless `skip_validation` is set.
 #[wasm_bindgen]
-pub async fn watchface_set_current(addr: String, watchface_id: String) -> Result<(), JsValue> {
+pub async fn watchface_set_current(
+    addr: String,
+    watchface_id: String,
+    skip_validation: Option<bool>,
+) -> Result<(), JsValue> {
     ensure_core_initialized();
+    if !skip_validation.unwrap_or(false) {
+        let rx = with_resource_system(&addr, |sys| Ok(sys.request_watchface_list()))
+            .await
+            .map_err(DeviceError::from_core)?;
+        let list = await_result_receiver(rx, "Watchface list response not received").await?;
+        if !list_contains(&list, &watchface_id) {
+            return Err(DeviceError::new(
+                ErrorCode::WatchfaceNotFound,
+                format!("Watchface {} is not installed", watchface_id),
+            )
+            .into());
+        }
+    }
+
     let id = watchface_id.clone();
-    with_watchface_system(&addr, move |sys| {
-        sys.set_watchface(&id);
-        Ok(())
-    })
+    serialized(
+        &addr,
+        with_watchface_system(&addr, move |sys| {
+            sys.set_watchface(&id);
+            Ok(())
+        }),
+    )
     .await
-    .map_err(|err| JsValue::from_str(&err))
+    .map_err(DeviceError::from_core)?;
+    remember_current(&addr, watchface_id);
+    paging::forget(&addr, Some("watchfaces"));
+    Ok(())
 }
 
 #[wasm_bindgen]
 pub async fn watchface_uninstall(addr: String, watchface_id: String) -> Result<(), JsValue> {
     ensure_core_initialized();
     let id = watchface_id.clone();
-    with_watchface_system(&addr, move |sys| {
-        sys.uninstall_watchface(&id);
+    serialized(
+        &addr,
+        with_watchface_system(&addr, move |sys| {
+            sys.uninstall_watchface(&id);
+            Ok(())
+        }),
+    )
+    .await
+    .map_err(DeviceError::from_core)?;
+    forget_current_if(&addr, &watchface_id);
+    paging::forget(&addr, Some("watchfaces"));
+    Ok(())
+}
+
+/// Removes each watchface in turn and refreshes the list once at the end,
+/// resolving with one `{ id, ok, error }` entry per id.
+#[wasm_bindgen]
+pub async fn watchface_uninstall_many(addr: String, ids: Vec<String>) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let results = serialized(&addr, async {
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let target = id.clone();
+            let result = with_watchface_system(&addr, move |sys| {
+                sys.uninstall_watchface(&target);
+                Ok(())
+            })
+            .await;
+            if result.is_ok() {
+                forget_current_if(&addr, &id);
+            }
+            results.push(ItemResult::new(id, result));
+        }
+        results
+    })
+    .await;
+    paging::forget(&addr, Some("watchfaces"));
+
+    let _ = with_resource_system(&addr, |sys| {
+        drop(sys.request_watchface_list());
+        Ok(())
+    })
+    .await;
+
+    to_js_value(&results).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// With `set_active` the face is made current as part of the install, before
+/// any other command reaches the device. The face is the `watchface_id` the
+/// install was sent with or, without one, the face that is new in the list
+/// compared to before. The install result comes back even if the face can't
+/// be made current, with `activated: false` and the reason in
+/// `activation_error`.
+#[wasm_bindgen]
+pub async fn watchface_install(
+    addr: String,
+    data: Uint8Array,
+    progress_cb: Option<Function>,
+    watchface_id: Option<String>,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    ensure_core_initialized();
+    let options = InstallOptions::from_js(options)?;
+
+    let file_data = data.to_vec();
+    let file_type = get_file_type(&file_data);
+    if file_type != FileType::WatchFace {
+        return Err(DeviceError::new(
+            ErrorCode::InvalidPackage,
+            format!("Expected a watchface package, got {:?}", file_type),
+        )
+        .into());
+    }
+    check_free_storage(&addr, file_data.len()).await?;
+
+    let set_active = options.set_active;
+    let result = with_install_slot(&addr, async {
+        let before = if set_active && watchface_id.is_none() {
+            Some(fetch_watchface_ids(&addr).await?)
+        } else {
+            None
+        };
+        let mut result = transfer_package(
+            addr.clone(),
+            MassDataType::Watchface,
+            file_data,
+            None,
+            progress_cb,
+            watchface_id,
+            options,
+        )
+        .await?;
+        if set_active {
+            let activation = activate_installed(&addr, &mut result, before).await;
+            result.activated = Some(activation.is_ok());
+            result.activation_error = activation.err();
+        }
+        Ok::<_, JsValue>(result)
+    })
+    .await?;
+    to_js_value(&result).map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+}
+
+/// Makes the face just installed current; the caller holds the install slot.
+async fn activate_installed(
+    addr: &str,
+    result: &mut InstallResult,
+    before: Option<Vec<String>>,
+) -> Result<(), DeviceError> {
+    if let Some(before) = before {
+        let after = fetch_watchface_ids(addr).await?;
+        result.assigned_id = after.into_iter().find(|id| !before.contains(id));
+    }
+    let Some(id) = result.assigned_id.clone() else {
+        return Err(DeviceError::new(
+            ErrorCode::WatchfaceNotFound,
+            "No new watchface in the device list to make current",
+        ));
+    };
+    let target = id.clone();
+    with_watchface_system(addr, move |sys| {
+        sys.set_watchface(&target);
         Ok(())
     })
     .await
-    .map_err(|err| JsValue::from_str(&err))
+    .map_err(DeviceError::from_core)?;
+    flush_writes(addr).await?;
+    remember_current(addr, id);
+    paging::forget(addr, Some("watchfaces"));
+    Ok(())
 }
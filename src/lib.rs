@@ -1,6 +1,11 @@
 #![cfg(any(target_arch = "wasm32", rust_analyzer))]
 
+pub mod ble;
+pub mod error;
 pub mod frontapi;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod spp;
+pub mod timer;
 
 pub use frontapi::*;
@@ -0,0 +1,298 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use async_channel::{Receiver, Sender, bounded};
+use corelib::device::xiaomi::r#type::ConnectType;
+use corelib::device::{self, DeviceConnectionInfo, DeviceKind};
+use serde::Deserialize;
+use serde_wasm_bindgen::from_value as from_js_value;
+use wasm_bindgen::JsValue;
+
+use crate::error::{DeviceError, ErrorCode};
+use crate::spp::xiaomi::{
+    ConnectionState, DEFAULT_DEVICE_NAME, DEFAULT_WRITE_QUEUE_DEPTH, SessionCallbacks,
+    WriteCommand, dispatch_packet,
+};
+use crate::timer::sleep_ms;
+
+/// One scripted exchange: once a frame starting with `expect` is written
+/// (any frame when it is omitted), `reply` is fed back after `delay_ms`.
+#[derive(Debug, Clone, Deserialize)]
+struct ScriptStep {
+    #[serde(default)]
+    expect: Option<String>,
+    #[serde(default)]
+    reply: Vec<String>,
+    #[serde(default)]
+    delay_ms: u32,
+}
+
+/// Frames are hex strings, the same format the `Send`/`Recv` trace logs
+/// print, so a script can be lifted from a capture of a real session.
+#[derive(Debug, Clone, Deserialize)]
+struct MockScript {
+    #[serde(default)]
+    addr: Option<String>,
+    #[serde(default)]
+    label: Option<String>,
+    steps: Vec<ScriptStep>,
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, DeviceError> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        return Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Odd number of hex digits in mock frame {hex}"),
+        ));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte = pair.iter().collect::<String>();
+            u8::from_str_radix(&byte, 16).map_err(|_| {
+                DeviceError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("Invalid hex in mock frame {hex}"),
+                )
+            })
+        })
+        .collect()
+}
+
+struct Step {
+    expect: Option<Vec<u8>>,
+    reply: Vec<Vec<u8>>,
+    delay_ms: u32,
+}
+
+/// In-memory transport that answers corelib's writes from a script instead
+/// of a port, for exercising the frontapi without hardware.
+pub struct XiaomiMock {
+    steps: Rc<RefCell<VecDeque<Step>>>,
+    device_addr: String,
+    device_label: Option<String>,
+    write_queue_depth: usize,
+    write_tx: Option<Sender<WriteCommand>>,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl XiaomiMock {
+    pub fn new(script: JsValue) -> Result<Self, DeviceError> {
+        let script: MockScript = from_js_value(script).map_err(|err| {
+            DeviceError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid mock script: {}", err),
+            )
+        })?;
+        let steps = script
+            .steps
+            .iter()
+            .map(|step| {
+                Ok(Step {
+                    expect: step.expect.as_deref().map(parse_hex).transpose()?,
+                    reply: step
+                        .reply
+                        .iter()
+                        .map(|frame| parse_hex(frame))
+                        .collect::<Result<_, _>>()?,
+                    delay_ms: step.delay_ms,
+                })
+            })
+            .collect::<Result<VecDeque<_>, DeviceError>>()?;
+
+        Ok(Self {
+            steps: Rc::new(RefCell::new(steps)),
+            device_addr: script
+                .addr
+                .unwrap_or_else(|| format!("mock-{}", js_sys::Date::now() as u64)),
+            device_label: script.label,
+            write_queue_depth: DEFAULT_WRITE_QUEUE_DEPTH,
+            write_tx: None,
+            runtime: None,
+        })
+    }
+
+    pub fn set_write_queue_depth(&mut self, depth: usize) {
+        self.write_queue_depth = depth.max(1);
+    }
+
+    pub fn write_sender(&self) -> Option<Sender<WriteCommand>> {
+        self.write_tx.clone()
+    }
+
+    pub fn device_addr(&self) -> &str {
+        &self.device_addr
+    }
+
+    pub fn device_label(&self) -> Option<&str> {
+        self.device_label.as_deref()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn start(
+        &mut self,
+        mut name: String,
+        addr_hint: String,
+        authkey: String,
+        sar_version: u32,
+        connect_type: ConnectType,
+        tx_win_overrun_allowance: u8,
+        callbacks: SessionCallbacks,
+    ) -> Result<DeviceConnectionInfo, DeviceError> {
+        let final_addr = if addr_hint.trim().is_empty() {
+            self.device_addr.clone()
+        } else {
+            addr_hint
+        };
+        (callbacks.on_state)(&final_addr, ConnectionState::Opening);
+
+        corelib::ecs::init_runtime_default();
+        let runtime = corelib::asyncrt::build_runtime();
+        let handle = runtime.handle().clone();
+
+        let (tx, rx): (Sender<WriteCommand>, Receiver<WriteCommand>) =
+            bounded(self.write_queue_depth);
+        self.write_tx = Some(tx.clone());
+
+        let steps = self.steps.clone();
+        let inbound_handle = callbacks.on_inbound.clone();
+        let device_id_for_writes = final_addr.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Ok(command) = rx.recv().await {
+                let frame = match command {
+                    WriteCommand::Data(data) => data,
+                    WriteCommand::Flush(ack) => {
+                        let _ = ack.send(());
+                        continue;
+                    }
+                };
+                let matched = {
+                    let mut pending = steps.borrow_mut();
+                    let matches = pending.front().is_some_and(|step| {
+                        step.expect
+                            .as_ref()
+                            .is_none_or(|expect| frame.starts_with(expect))
+                    });
+                    if matches { pending.pop_front() } else { None }
+                };
+                let Some(step) = matched else {
+                    log::debug!(
+                        "[wasm] Mock ignored frame: {}",
+                        corelib::tools::to_hex_string(&frame)
+                    );
+                    continue;
+                };
+
+                let packet_handle = handle.clone();
+                let inbound_handle = inbound_handle.clone();
+                let device_id = device_id_for_writes.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    if step.delay_ms > 0 {
+                        sleep_ms(step.delay_ms).await;
+                    }
+                    for reply in step.reply {
+                        if let Some(inbound) = inbound_handle.as_ref() {
+                            inbound(&device_id, &reply);
+                        }
+                        dispatch_packet(&packet_handle, &device_id, reply);
+                    }
+                });
+            }
+        });
+
+        if name.is_empty() {
+            name = self
+                .device_label
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DEVICE_NAME.to_string());
+        }
+
+        (callbacks.on_state)(&final_addr, ConnectionState::Authenticating);
+        let device_info = device::create_device(
+            runtime.handle().clone(),
+            DeviceKind::Xiaomi,
+            name,
+            final_addr.clone(),
+            authkey,
+            sar_version,
+            connect_type,
+            Some(tx_win_overrun_allowance),
+            None,
+            None,
+            false,
+            {
+                let tx = tx.clone();
+                move |data: Vec<Vec<u8>>| {
+                    let tx = tx.clone();
+                    async move {
+                        for item in data {
+                            if !item.is_empty() {
+                                let _ = tx.send(WriteCommand::Data(item)).await;
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            },
+        )
+        .await
+        .map_err(|err| {
+            (callbacks.on_state)(&final_addr, ConnectionState::Error);
+            DeviceError::from_core_connect(err)
+        })?;
+
+        (callbacks.on_state)(&final_addr, ConnectionState::Ready);
+        self.runtime = Some(runtime);
+        Ok(device_info)
+    }
+
+    pub async fn disconnect(mut self) -> Result<(), JsValue> {
+        if let Some(tx) = self.write_tx.take() {
+            tx.close();
+        }
+        self.runtime.take();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn parse_hex_ignores_whitespace() {
+        assert_eq!(parse_hex("a5 5a\n01ff").unwrap(), [0xa5, 0x5a, 0x01, 0xff]);
+        assert!(parse_hex("").unwrap().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn parse_hex_rejects_malformed_frames() {
+        assert_eq!(
+            parse_hex("abc").unwrap_err().code,
+            ErrorCode::InvalidArgument
+        );
+        assert_eq!(
+            parse_hex("zz").unwrap_err().code,
+            ErrorCode::InvalidArgument
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn script_frames_are_validated_up_front() {
+        let script = serde_json::json!({
+            "addr": "mock-1",
+            "steps": [{ "reply": ["0g"] }],
+        })
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .unwrap();
+        let err = XiaomiMock::new(script).err().unwrap();
+        assert_eq!(err.code, ErrorCode::InvalidArgument);
+    }
+}
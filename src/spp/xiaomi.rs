@@ -1,16 +1,81 @@
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
-use async_channel::{Receiver, Sender, unbounded};
+use async_channel::{Receiver, Sender, bounded};
 use corelib::device::xiaomi::r#type::ConnectType;
 use corelib::device::{self, DeviceConnectionInfo, DeviceKind};
-use js_sys::{Reflect, Uint8Array};
+use js_sys::{Array, Reflect, Uint8Array};
+use serde::Serialize;
+use tokio::sync::oneshot;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
     Navigator, ReadableStream, ReadableStreamDefaultReader, Serial, SerialOptions, SerialPort,
-    SerialPortInfo, SerialPortRequestOptions, WritableStream, WritableStreamDefaultWriter, window,
+    SerialPortFilter, SerialPortInfo, SerialPortRequestOptions, WritableStream,
+    WritableStreamDefaultWriter, window,
 };
 
+use crate::error::{DeviceError, ErrorCode, js_error_message, picker_error};
+use crate::timer::sleep_ms;
+
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// Entity name used when the host connects without a name.
+pub(crate) const DEFAULT_DEVICE_NAME: &str = "Xiaomi Device";
+
+/// Read and write buffer size for `port.open`. Web Serial defaults to 255
+/// bytes, which an install burst can overrun before the read loop drains it.
+pub const DEFAULT_BUFFER_SIZE: u32 = 16 * 1024;
+/// Largest buffer Web Serial accepts.
+const MAX_BUFFER_SIZE: u32 = 16 * 1024 * 1024 - 1;
+
+const WRITE_DRAIN_TIMEOUT_MS: u32 = 2_000;
+
+/// Failed reads retried before the link is declared dead.
+pub const DEFAULT_READ_RETRIES: u32 = 2;
+const READ_RETRY_DELAY_MS: u32 = 100;
+
+/// Frames queued for the serial writer before `create_device`'s send callback
+/// has to wait, keeping large installs from buffering unbounded in the tab.
+pub const DEFAULT_WRITE_QUEUE_DEPTH: usize = 64;
+
+const STANDARD_BAUD_RATES: [u32; 15] = [
+    1200, 2400, 4800, 9600, 14400, 19200, 38400, 57600, 115200, 230400, 460800, 921600, 1000000,
+    1500000, 2000000,
+];
+
+pub fn resolve_baud_rate(baud_rate: Option<u32>) -> Result<u32, DeviceError> {
+    let rate = baud_rate.unwrap_or(DEFAULT_BAUD_RATE);
+    if STANDARD_BAUD_RATES.contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unsupported baud rate: {rate}"),
+        ))
+    }
+}
+
+pub fn resolve_buffer_size(buffer_size: Option<u32>) -> Result<u32, DeviceError> {
+    match buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE) {
+        size @ 1..=MAX_BUFFER_SIZE => Ok(size),
+        size => Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unsupported buffer size: {size}"),
+        )),
+    }
+}
+
+pub fn resolve_write_queue_depth(depth: Option<usize>) -> Result<usize, DeviceError> {
+    match depth.unwrap_or(DEFAULT_WRITE_QUEUE_DEPTH) {
+        0 => Err(DeviceError::new(
+            ErrorCode::InvalidArgument,
+            "Write queue depth must be at least 1",
+        )),
+        depth => Ok(depth),
+    }
+}
+
 fn read_optional_string(info: &JsValue, key: &str) -> Option<String> {
     Reflect::get(info, &JsValue::from_str(key))
         .ok()
@@ -25,30 +90,201 @@ fn read_optional_u16(info: &JsValue, key: &str) -> Option<u16> {
         .map(|num| num as u16)
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortIdentity {
+    pub serial_number: Option<String>,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+}
+
+impl PortIdentity {
+    pub fn of(port: &SerialPort) -> Self {
+        let info: SerialPortInfo = port.get_info();
+        let info_js: JsValue = info.into();
+        Self {
+            serial_number: read_optional_string(&info_js, "serialNumber"),
+            vendor_id: read_optional_u16(&info_js, "usbVendorId"),
+            product_id: read_optional_u16(&info_js, "usbProductId"),
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        self.serial_number.is_some() || (self.vendor_id.is_some() && self.product_id.is_some())
+    }
+}
+
+fn serial() -> Serial {
+    let nav: Navigator = window().unwrap().navigator();
+    nav.serial()
+}
+
+/// Ports the user has already granted to this origin.
+pub async fn granted_ports() -> Result<Vec<SerialPort>, DeviceError> {
+    let ports = JsFuture::from(serial().get_ports())
+        .await
+        .map_err(|err| DeviceError::from_js(ErrorCode::PortOpenFailed, &err))?;
+    let ports: Array = ports.unchecked_into();
+    Ok(ports
+        .iter()
+        .map(|port| port.unchecked_into::<SerialPort>())
+        .collect())
+}
+
+async fn find_granted_port(identity: &PortIdentity) -> Result<Option<SerialPort>, DeviceError> {
+    if !identity.is_known() {
+        return Ok(None);
+    }
+    Ok(granted_ports()
+        .await?
+        .into_iter()
+        .find(|port| PortIdentity::of(port) == *identity))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ConnectionState {
+    Opening,
+    Authenticating,
+    Ready,
+    Reconnecting,
+    Disconnected,
+    Error,
+}
+
+/// Why a session ended, reported with `device-disconnected`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    User,
+    Remote,
+    Error,
+    Timeout,
+    Idle,
+    Reconnecting,
+    /// Auto reconnect used up its attempts. Reported as `reconnect-failed`
+    /// rather than in snake_case like the other reasons.
+    #[serde(rename = "reconnect-failed")]
+    ReconnectFailed,
+}
+
+thread_local! {
+    static DISPATCH_ADDR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Hands an inbound frame to corelib, remembering which device it came from
+/// so a panic while parsing can be attributed.
+pub fn dispatch_packet(handle: &tokio::runtime::Handle, addr: &str, data: Vec<u8>) {
+    DISPATCH_ADDR.with(|cell| *cell.borrow_mut() = Some(addr.to_string()));
+    corelib::device::xiaomi::packet::dispatcher::on_packet(handle.clone(), addr.to_string(), data);
+    DISPATCH_ADDR.with(|cell| *cell.borrow_mut() = None);
+}
+
+/// The device whose frame is being dispatched right now, if any.
+pub fn current_dispatch_addr() -> Option<String> {
+    DISPATCH_ADDR.with(|cell| cell.borrow().clone())
+}
+
+/// Items on a transport's write queue.
+pub enum WriteCommand {
+    Data(Vec<u8>),
+    /// Answered once every command queued before it has been written.
+    Flush(oneshot::Sender<()>),
+}
+
+pub type StateCallback = Rc<dyn Fn(&str, ConnectionState)>;
+pub type InboundCallback = Rc<dyn Fn(&str, &[u8])>;
+/// Gets each outgoing chunk right before it is written and returns the bytes to send.
+pub type OutboundCallback = Rc<dyn Fn(&str, Vec<u8>) -> Vec<u8>>;
+/// Called with `(addr, direction, message)` when the link fails mid-session.
+pub type LinkErrorCallback = Rc<dyn Fn(&str, &str, &str)>;
+
+#[derive(Clone)]
+pub struct SessionCallbacks {
+    pub on_disconnect: Rc<dyn Fn(String, DisconnectReason)>,
+    pub on_state: StateCallback,
+    pub on_link_error: LinkErrorCallback,
+    /// Sees every chunk read from the port before it is dispatched.
+    pub on_inbound: Option<InboundCallback>,
+    pub on_outbound: Option<OutboundCallback>,
+}
+
 pub struct XiaomiSpp {
     port: SerialPort,
-    reader: Option<ReadableStreamDefaultReader>,
+    identity: PortIdentity,
+    /// Shared with the read loop, which swaps in a fresh reader after a
+    /// recoverable read error.
+    reader: Rc<RefCell<Option<ReadableStreamDefaultReader>>>,
     writer: Option<WritableStreamDefaultWriter>,
     device_addr: String,
     device_label: Option<String>,
+    write_queue_depth: usize,
+    read_retries: u32,
+    write_tx: Option<Sender<WriteCommand>>,
+    write_done: Option<oneshot::Receiver<()>>,
+    closing: Rc<Cell<bool>>,
     runtime: Option<tokio::runtime::Runtime>,
 }
 
 impl XiaomiSpp {
-    pub async fn new(baud_rate: Option<u32>) -> Result<Self, JsValue> {
-        let nav: Navigator = window().unwrap().navigator();
-        let serial: Serial = nav.serial();
+    pub async fn new(
+        baud_rate: Option<u32>,
+        buffer_size: Option<u32>,
+        filters: &[(u16, u16)],
+    ) -> Result<Self, DeviceError> {
+        let baud_rate = resolve_baud_rate(baud_rate)?;
+        let buffer_size = resolve_buffer_size(buffer_size)?;
         let opts = SerialPortRequestOptions::new();
+        if !filters.is_empty() {
+            let port_filters = filters
+                .iter()
+                .map(|(vendor, product)| {
+                    let filter = SerialPortFilter::new();
+                    filter.set_usb_vendor_id(*vendor);
+                    filter.set_usb_product_id(*product);
+                    filter
+                })
+                .collect::<Vec<_>>();
+            opts.set_filters(&port_filters);
+        }
 
-        let port_val = JsFuture::from(serial.request_port_with_options(&opts)).await?;
-        let port: SerialPort = port_val.unchecked_into();
+        let port_val = JsFuture::from(serial().request_port_with_options(&opts))
+            .await
+            .map_err(|err| picker_error(&err))?;
 
-        let info: SerialPortInfo = port.get_info();
-        let info_js: JsValue = info.into();
+        Self::open(port_val.unchecked_into(), baud_rate, buffer_size).await
+    }
 
-        let serial_number = read_optional_string(&info_js, "serialNumber");
-        let vendor_id = read_optional_u16(&info_js, "usbVendorId");
-        let product_id = read_optional_u16(&info_js, "usbProductId");
+    /// Opens a previously used port without showing the picker, preferring a
+    /// granted port with the same identity in case the browser re-enumerated it.
+    pub async fn reopen(
+        cached: &SerialPort,
+        identity: &PortIdentity,
+        baud_rate: Option<u32>,
+        buffer_size: Option<u32>,
+    ) -> Result<Self, DeviceError> {
+        let baud_rate = resolve_baud_rate(baud_rate)?;
+        let buffer_size = resolve_buffer_size(buffer_size)?;
+        let port = find_granted_port(identity)
+            .await?
+            .unwrap_or_else(|| cached.clone());
+        Self::open(port, baud_rate, buffer_size).await
+    }
+
+    /// Opens a specific granted port without showing the picker.
+    pub async fn from_port(
+        port: SerialPort,
+        baud_rate: Option<u32>,
+        buffer_size: Option<u32>,
+    ) -> Result<Self, DeviceError> {
+        let baud_rate = resolve_baud_rate(baud_rate)?;
+        let buffer_size = resolve_buffer_size(buffer_size)?;
+        Self::open(port, baud_rate, buffer_size).await
+    }
+
+    async fn open(port: SerialPort, baud_rate: u32, buffer_size: u32) -> Result<Self, DeviceError> {
+        let identity = PortIdentity::of(&port);
+        let serial_number = identity.serial_number.clone();
+        let vendor_id = identity.vendor_id;
+        let product_id = identity.product_id;
 
         let device_addr = if let Some(serial_num) = serial_number.clone() {
             format!("serial:{serial_num}")
@@ -64,19 +300,49 @@ impl XiaomiSpp {
                 .map(|(v, p)| format!("USB {:04x}:{:04x}", v, p))
         });
 
-        let open_opts = SerialOptions::new(baud_rate.unwrap_or(115200));
-        JsFuture::from(port.open(&open_opts)).await?;
+        let open_opts = SerialOptions::new(baud_rate);
+        open_opts.set_buffer_size(buffer_size);
+        JsFuture::from(port.open(&open_opts))
+            .await
+            .map_err(|err| DeviceError::from_js(ErrorCode::PortOpenFailed, &err))?;
 
         Ok(Self {
             port,
-            reader: None,
+            identity,
+            reader: Rc::new(RefCell::new(None)),
             writer: None,
             device_addr,
             device_label,
+            write_queue_depth: DEFAULT_WRITE_QUEUE_DEPTH,
+            read_retries: DEFAULT_READ_RETRIES,
+            write_tx: None,
+            write_done: None,
+            closing: Rc::new(Cell::new(false)),
             runtime: None,
         })
     }
 
+    pub fn port(&self) -> &SerialPort {
+        &self.port
+    }
+
+    pub fn identity(&self) -> &PortIdentity {
+        &self.identity
+    }
+
+    pub fn set_write_queue_depth(&mut self, depth: usize) {
+        self.write_queue_depth = depth.max(1);
+    }
+
+    pub fn set_read_retries(&mut self, retries: u32) {
+        self.read_retries = retries;
+    }
+
+    /// Handle to the queue feeding the serial writer, shared with corelib.
+    pub fn write_sender(&self) -> Option<Sender<WriteCommand>> {
+        self.write_tx.clone()
+    }
+
     pub fn device_addr(&self) -> &str {
         &self.device_addr
     }
@@ -94,6 +360,7 @@ impl XiaomiSpp {
         Ok(self.writer.as_ref().unwrap().clone())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn start(
         &mut self,
         mut name: String,
@@ -102,57 +369,131 @@ impl XiaomiSpp {
         sar_version: u32,
         connect_type: ConnectType,
         tx_win_overrun_allowance: u8,
-        disconnect_cb: Rc<dyn Fn(String)>,
-    ) -> Result<DeviceConnectionInfo, JsValue> {
+        callbacks: SessionCallbacks,
+    ) -> Result<DeviceConnectionInfo, DeviceError> {
+        let final_addr = if addr_hint.trim().is_empty() {
+            self.device_addr.clone()
+        } else {
+            addr_hint
+        };
+        (callbacks.on_state)(&final_addr, ConnectionState::Opening);
+
         let readable: ReadableStream = self.port.readable();
         let reader: ReadableStreamDefaultReader = readable.get_reader().unchecked_into();
-        self.reader = Some(reader.clone());
-
-        let writer_handle = self.ensure_writer()?;
-        let (tx, rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = unbounded();
+        *self.reader.borrow_mut() = Some(reader.clone());
 
+        let writer_handle = match self.ensure_writer() {
+            Ok(writer) => writer,
+            Err(err) => {
+                (callbacks.on_state)(&final_addr, ConnectionState::Error);
+                return Err(DeviceError::from_js(ErrorCode::PortOpenFailed, &err));
+            }
+        };
+        let (tx, rx): (Sender<WriteCommand>, Receiver<WriteCommand>) =
+            bounded(self.write_queue_depth);
+        let (done_tx, done_rx) = oneshot::channel();
+        self.write_tx = Some(tx.clone());
+        self.write_done = Some(done_rx);
+
+        let write_error_handle = callbacks.on_link_error.clone();
+        let write_disconnect_handle = callbacks.on_disconnect.clone();
+        let outbound_handle = callbacks.on_outbound.clone();
+        let device_id_for_writes = final_addr.clone();
         wasm_bindgen_futures::spawn_local(async move {
-            while let Ok(data) = rx.recv().await {
+            while let Ok(command) = rx.recv().await {
+                let data = match command {
+                    WriteCommand::Data(data) => data,
+                    WriteCommand::Flush(ack) => {
+                        // Each write is awaited, so everything before this is out.
+                        let _ = ack.send(());
+                        continue;
+                    }
+                };
+                let data = match outbound_handle.as_ref() {
+                    Some(outbound) => outbound(&device_id_for_writes, data),
+                    None => data,
+                };
                 let chunk = Uint8Array::from(data.as_slice());
                 if let Err(err) = JsFuture::from(writer_handle.write_with_chunk(&chunk)).await {
                     web_sys::console::warn_1(&JsValue::from_str(&format!(
                         "[wasm] Failed to write to serial port: {:?}",
                         err
                     )));
+                    write_error_handle(&device_id_for_writes, "write", &js_error_message(&err));
+                    write_disconnect_handle(device_id_for_writes.clone(), DisconnectReason::Error);
                     break;
                 }
             }
+            let _ = done_tx.send(());
         });
 
         corelib::ecs::init_runtime_default();
         let runtime = corelib::asyncrt::build_runtime();
         let handle = runtime.handle().clone();
 
+        // The port label is a serial number or USB id, which makes a poor
+        // display name; it stays available as the session's transport label.
         if name.is_empty() {
-            name = self
-                .device_label
-                .clone()
-                .unwrap_or_else(|| "Bluetooth Device".to_string());
+            name = DEFAULT_DEVICE_NAME.to_string();
         }
 
-        let final_addr = if addr_hint.trim().is_empty() {
-            self.device_addr.clone()
-        } else {
-            addr_hint
-        };
-
         let packet_handle = handle.clone();
-        let disconnect_handle = disconnect_cb.clone();
+        let disconnect_handle = callbacks.on_disconnect.clone();
+        let read_error_handle = callbacks.on_link_error.clone();
+        let inbound_handle = callbacks.on_inbound.clone();
         let device_id_for_loop = final_addr.clone();
-        let reader_for_loop = reader.clone();
+        let closing = self.closing.clone();
+        let reader_slot = self.reader.clone();
+        let port_for_loop = self.port.clone();
+        let read_retries = self.read_retries;
 
         wasm_bindgen_futures::spawn_local(async move {
+            let mut failed_reads = 0;
             loop {
-                let read_res = JsFuture::from(reader_for_loop.read()).await;
-                let Ok(val) = read_res else {
-                    disconnect_handle(device_id_for_loop.clone());
+                let Some(reader_for_loop) = reader_slot.borrow().clone() else {
                     break;
                 };
+                let val = match JsFuture::from(reader_for_loop.read()).await {
+                    Ok(val) => {
+                        failed_reads = 0;
+                        val
+                    }
+                    Err(err) => {
+                        // Releasing the reader in `disconnect` rejects the pending read;
+                        // a local close must not tear down a session reusing this addr.
+                        if closing.get() {
+                            break;
+                        }
+                        failed_reads += 1;
+                        if failed_reads <= read_retries {
+                            web_sys::console::warn_1(&JsValue::from_str(&format!(
+                                "[wasm] Serial read failed ({}/{}), retrying: {}",
+                                failed_reads,
+                                read_retries,
+                                js_error_message(&err)
+                            )));
+                            sleep_ms(READ_RETRY_DELAY_MS).await;
+                            // After a non-fatal error the port hands out a new readable
+                            // stream; a fatal one leaves `readable` null.
+                            let readable: JsValue = port_for_loop.readable().into();
+                            if !closing.get() && !readable.is_null() {
+                                reader_for_loop.release_lock();
+                                let next = readable
+                                    .unchecked_into::<ReadableStream>()
+                                    .get_reader()
+                                    .unchecked_into::<ReadableStreamDefaultReader>();
+                                *reader_slot.borrow_mut() = Some(next);
+                                continue;
+                            }
+                            if closing.get() {
+                                break;
+                            }
+                        }
+                        read_error_handle(&device_id_for_loop, "read", &js_error_message(&err));
+                        disconnect_handle(device_id_for_loop.clone(), DisconnectReason::Error);
+                        break;
+                    }
+                };
 
                 let done = Reflect::get(&val, &JsValue::from_str("done"))
                     .ok()
@@ -160,8 +501,10 @@ impl XiaomiSpp {
                     .unwrap_or(false);
 
                 if done {
-                    let _ = reader_for_loop.release_lock();
-                    disconnect_handle(device_id_for_loop.clone());
+                    reader_for_loop.release_lock();
+                    if !closing.get() {
+                        disconnect_handle(device_id_for_loop.clone(), DisconnectReason::Remote);
+                    }
                     break;
                 }
 
@@ -173,15 +516,15 @@ impl XiaomiSpp {
                 }
 
                 let data: Vec<u8> = Uint8Array::new(&chunk).to_vec();
-                //log::info!("[wasm] Recv: {}", corelib::tools::to_hex_string(&data));
-                corelib::device::xiaomi::packet::dispatcher::on_packet(
-                    packet_handle.clone(),
-                    device_id_for_loop.clone(),
-                    data,
-                );
+                log::trace!("[wasm] Recv: {}", corelib::tools::to_hex_string(&data));
+                if let Some(inbound) = inbound_handle.as_ref() {
+                    inbound(&device_id_for_loop, &data);
+                }
+                dispatch_packet(&packet_handle, &device_id_for_loop, data);
             }
         });
 
+        (callbacks.on_state)(&final_addr, ConnectionState::Authenticating);
         let device_info_res = device::create_device(
             handle.clone(),
             DeviceKind::Xiaomi,
@@ -199,14 +542,14 @@ impl XiaomiSpp {
                 move |data: Vec<Vec<u8>>| {
                     let tx = tx.clone();
                     async move {
-                        //log::info!("[wasm] Send: {}", corelib::tools::to_hex_string(&data));
                         let total_len = data.iter().map(|item| item.len()).sum::<usize>();
                         let mut merged = Vec::with_capacity(total_len);
                         for item in data {
                             merged.extend_from_slice(&item);
                         }
                         if !merged.is_empty() {
-                            let _ = tx.send(merged).await;
+                            log::trace!("[wasm] Send: {}", corelib::tools::to_hex_string(&merged));
+                            let _ = tx.send(WriteCommand::Data(merged)).await;
                         }
                         Ok(())
                     }
@@ -222,26 +565,78 @@ impl XiaomiSpp {
                     "[wasm] create_device failed: {}",
                     err
                 )));
-                let _ = reader.release_lock();
+                // Set first so the read loop doesn't report the released
+                // reader as a lost link.
+                self.closing.set(true);
+                if let Some(tx) = self.write_tx.take() {
+                    tx.close();
+                }
+                if let Some(reader) = self.reader.borrow_mut().take() {
+                    reader.release_lock();
+                }
                 let _ = JsFuture::from(self.port.close()).await;
-                return Err(JsValue::from_str(&err.to_string()));
+                (callbacks.on_state)(&final_addr, ConnectionState::Error);
+                return Err(DeviceError::from_core_connect(err));
             }
         };
 
+        (callbacks.on_state)(&final_addr, ConnectionState::Ready);
+
         self.runtime = Some(runtime);
 
         Ok(device_info)
     }
 
     pub async fn disconnect(mut self) -> Result<(), JsValue> {
+        self.closing.set(true);
+        // Closing the channel lets the write loop flush what is already queued.
+        if let Some(tx) = self.write_tx.take() {
+            tx.close();
+        }
+        if let Some(done) = self.write_done.take() {
+            tokio::select! {
+                biased;
+                _ = done => {}
+                _ = sleep_ms(WRITE_DRAIN_TIMEOUT_MS) => {
+                    web_sys::console::warn_1(&JsValue::from_str(
+                        "[wasm] Timed out flushing serial writes before disconnect",
+                    ));
+                }
+            }
+        }
         if let Some(writer) = self.writer.take() {
             let _ = JsFuture::from(writer.close()).await;
         }
-        if let Some(reader) = self.reader.take() {
-            let _ = reader.release_lock();
+        if let Some(reader) = self.reader.borrow_mut().take() {
+            reader.release_lock();
         }
         let _ = JsFuture::from(self.port.close()).await;
         self.runtime.take();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn baud_rate_defaults_and_rejects_non_standard_rates() {
+        assert_eq!(resolve_baud_rate(None).unwrap(), DEFAULT_BAUD_RATE);
+        assert_eq!(resolve_baud_rate(Some(921_600)).unwrap(), 921_600);
+        assert_eq!(
+            resolve_baud_rate(Some(12_345)).unwrap_err().code,
+            ErrorCode::InvalidArgument
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn buffer_size_and_queue_depth_must_be_positive() {
+        assert_eq!(resolve_buffer_size(None).unwrap(), DEFAULT_BUFFER_SIZE);
+        assert!(resolve_buffer_size(Some(0)).is_err());
+        assert!(resolve_buffer_size(Some(MAX_BUFFER_SIZE + 1)).is_err());
+        assert!(resolve_write_queue_depth(Some(0)).is_err());
+        assert_eq!(resolve_write_queue_depth(Some(8)).unwrap(), 8);
+    }
+}
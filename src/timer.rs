@@ -0,0 +1,15 @@
+use js_sys::Promise;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+
+/// Resolves after `ms` milliseconds using the browser's `setTimeout`, since
+/// tokio timers have no driver on wasm32.
+pub async fn sleep_ms(ms: u32) {
+    let delay = ms.min(i32::MAX as u32) as i32;
+    let promise = Promise::new(&mut |resolve, _reject| {
+        let _ = window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, delay);
+    });
+    let _ = JsFuture::from(promise).await;
+}